@@ -0,0 +1,586 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tauri::{Emitter, Manager};
+
+use crate::downloader::{self, ProgressSink};
+use crate::models::{
+  BundleTaskInput, CollectionDiff, CollectionExportReport, CollectionListing, CollectionManifestMeta, DownloadTaskInput,
+  ManifestError, TaskEvent, TaskRecord, TaskState,
+};
+use crate::task_store::{self, TaskStore};
+use crate::{
+  bundle_index, bundle_task, chart_index, collections, library, overlay_watch, persist_task, push_log, update_task,
+  AppRuntimeState, InnerState, TaskControl,
+};
+
+/// 把任务进度转发为前端的 `task_event` 事件。
+pub struct TauriProgressSink(pub tauri::AppHandle);
+
+impl ProgressSink for TauriProgressSink {
+  fn emit(&self, event: TaskEvent) {
+    let _ = self.0.emit("task_event", event);
+  }
+}
+
+fn restore_task_history(state: &Arc<InnerState>, dir: PathBuf) {
+  let store = match TaskStore::open(dir) {
+    Ok(v) => v,
+    Err(e) => {
+      eprintln!("open task store failed: {:#}", e);
+      return;
+    }
+  };
+
+  for mut record in store.load_all() {
+    // 上次退出时仍在运行的任务已无法继续，按失败处理并回写。
+    if matches!(record.state.status.as_str(), "pending" | "running" | "paused") {
+      record.state.status = "failed".to_string();
+      record.state.message = Some("应用退出时任务尚未结束".to_string());
+      let _ = store.save(&record);
+    }
+    let task_id = record.state.task_id.clone();
+    state.task_inputs.lock().insert(task_id.clone(), record.input);
+    state.tasks.lock().insert(task_id, record.state);
+  }
+
+  *state.task_store.lock() = Some(store);
+}
+
+fn resource_dir(app: &tauri::AppHandle) -> Option<PathBuf> {
+  app.path().resource_dir().ok()
+}
+
+#[tauri::command]
+async fn list_builtin_collections(
+  app: tauri::AppHandle,
+  state: tauri::State<'_, AppRuntimeState>,
+) -> Result<CollectionListing, String> {
+  let overlay = state.0.overlay_collections_dir.lock().clone();
+  collections::list_collections(resource_dir(&app).as_deref(), overlay)
+}
+
+/// 设置外部 collections 目录，并在目录变化时重新开始监视，清单变化以 `collections_changed` 事件通知前端。
+fn set_overlay_dir(app: &tauri::AppHandle, state: &InnerState, root: PathBuf) {
+  let mut current = state.overlay_collections_dir.lock();
+  let mut watcher = state.overlay_watcher.lock();
+  if current.as_ref() == Some(&root) && watcher.is_some() {
+    return;
+  }
+  *current = Some(root.clone());
+
+  let app = app.clone();
+  let spawned = overlay_watch::spawn_watcher(root, move |change| {
+    let _ = app.emit(overlay_watch::COLLECTIONS_CHANGED_EVENT, change);
+  });
+  *watcher = match spawned {
+    Ok(v) => Some(v),
+    Err(e) => {
+      eprintln!("watch collections failed: {:#}", e);
+      None
+    }
+  };
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RefreshResult {
+  manifest_count: usize,
+  dir: String,
+  /// 目录中无法解析的清单；有效清单照常加载。
+  errors: Vec<ManifestError>,
+}
+
+#[tauri::command]
+async fn refresh_collections_from_dir(
+  app: tauri::AppHandle,
+  dir: String,
+  state: tauri::State<'_, AppRuntimeState>,
+) -> Result<RefreshResult, String> {
+  let path = PathBuf::from(dir.trim());
+  let listing = collections::validate_collections_dir(&path)?;
+  set_overlay_dir(&app, &state.0, path.clone());
+  Ok(RefreshResult {
+    manifest_count: listing.manifests.len(),
+    dir: path.to_string_lossy().to_string(),
+    errors: listing.errors,
+  })
+}
+
+fn non_empty_path(value: Option<String>) -> Option<PathBuf> {
+  value
+    .map(|s| s.trim().to_string())
+    .filter(|s| !s.is_empty())
+    .map(PathBuf::from)
+}
+
+/// 默认比较内置快照（old）与当前的外部 collections 目录（new）。
+#[tauri::command]
+async fn diff_collections(
+  app: tauri::AppHandle,
+  state: tauri::State<'_, AppRuntimeState>,
+  old_root: Option<String>,
+  new_root: Option<String>,
+) -> Result<CollectionDiff, String> {
+  let old_root = match non_empty_path(old_root) {
+    Some(p) => p,
+    None => collections::builtin_collections_dir(resource_dir(&app).as_deref())?,
+  };
+  let new_root = match non_empty_path(new_root) {
+    Some(p) => p,
+    None => state
+      .0
+      .overlay_collections_dir
+      .lock()
+      .clone()
+      .ok_or("newRoot is empty and no collections directory has been loaded")?,
+  };
+  tauri::async_runtime::spawn_blocking(move || collections::diff_collections(&old_root, &new_root))
+    .await
+    .map_err(|e| format!("diff task failed: {}", e))?
+}
+
+/// 清单写入的目录：参数优先，其次为已加载的外部目录；写入成功后设为当前外部目录（见 `set_overlay_dir`）。
+fn authoring_root(state: &InnerState, root: Option<String>) -> Result<PathBuf, String> {
+  non_empty_path(root)
+    .or_else(|| state.overlay_collections_dir.lock().clone())
+    .ok_or_else(|| "root is empty and no collections directory has been loaded".to_string())
+}
+
+#[tauri::command]
+async fn create_manifest(
+  app: tauri::AppHandle,
+  state: tauri::State<'_, AppRuntimeState>,
+  root: Option<String>,
+  folder: String,
+  name: String,
+  level_ids: Vec<String>,
+) -> Result<CollectionManifestMeta, String> {
+  let root = authoring_root(&state.0, root)?;
+  let meta = collections::create_manifest(&root, &folder, &name, &level_ids)?;
+  set_overlay_dir(&app, &state.0, root);
+  Ok(meta)
+}
+
+/// 内置清单不会被改动：修改结果写到外部目录中相同的相对路径。
+#[tauri::command]
+async fn update_manifest(
+  app: tauri::AppHandle,
+  state: tauri::State<'_, AppRuntimeState>,
+  root: Option<String>,
+  path: String,
+  add_ids: Option<Vec<String>>,
+  remove_ids: Option<Vec<String>>,
+  name: Option<String>,
+) -> Result<CollectionManifestMeta, String> {
+  let root = authoring_root(&state.0, root)?;
+  let source = PathBuf::from(path.trim());
+  let target = if source.starts_with(&root) {
+    source.clone()
+  } else {
+    let builtin = collections::builtin_collections_dir(resource_dir(&app).as_deref())?;
+    let rel = source
+      .strip_prefix(&builtin)
+      .map_err(|_| format!("manifest is not under a collections directory: {}", source.display()))?;
+    root.join(rel)
+  };
+  let meta = collections::update_manifest(
+    &root,
+    &source,
+    &target,
+    &add_ids.unwrap_or_default(),
+    &remove_ids.unwrap_or_default(),
+    name.as_deref(),
+  )?;
+  set_overlay_dir(&app, &state.0, root);
+  Ok(meta)
+}
+
+#[tauri::command]
+async fn combine_manifests(
+  app: tauri::AppHandle,
+  state: tauri::State<'_, AppRuntimeState>,
+  root: Option<String>,
+  op: String,
+  paths: Vec<String>,
+  folder: String,
+  name: String,
+) -> Result<CollectionManifestMeta, String> {
+  let root = authoring_root(&state.0, root)?;
+  let op = collections::SetOp::parse(&op)?;
+  let sources: Vec<PathBuf> = paths.iter().map(|p| PathBuf::from(p.trim())).collect();
+  let meta = collections::combine_manifests(&root, op, &sources, &folder, &name)?;
+  set_overlay_dir(&app, &state.0, root);
+  Ok(meta)
+}
+
+/// `local_dir` 为谱面输出目录时，清单只保留本地已有的谱面。
+#[tauri::command]
+async fn export_collections(
+  target_dir: String,
+  manifest_paths: Vec<String>,
+  local_dir: Option<String>,
+) -> Result<CollectionExportReport, String> {
+  let target = PathBuf::from(target_dir.trim());
+  let local_dir = non_empty_path(local_dir);
+  let sources: Vec<PathBuf> = manifest_paths.iter().map(|p| PathBuf::from(p.trim())).collect();
+  tauri::async_runtime::spawn_blocking(move || {
+    collections::export_collections(&sources, &target, local_dir.as_deref())
+  })
+  .await
+  .map_err(|e| format!("export task failed: {}", e))?
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StartTaskResult {
+  task_id: String,
+}
+
+#[tauri::command]
+async fn start_download_task(
+  app: tauri::AppHandle,
+  state: tauri::State<'_, AppRuntimeState>,
+  input: DownloadTaskInput,
+) -> Result<StartTaskResult, String> {
+  let has_level_ids = input.level_ids.as_ref().is_some_and(|ids| !ids.is_empty());
+  if input.selected_manifest_paths.is_empty() && !has_level_ids {
+    return Err("selectedManifestPaths is empty".to_string());
+  }
+
+  let task_id = spawn_download_task(app, &state.0, input, None);
+  Ok(StartTaskResult { task_id })
+}
+
+fn spawn_download_task(
+  app: tauri::AppHandle,
+  state: &Arc<InnerState>,
+  input: DownloadTaskInput,
+  retry_of: Option<String>,
+) -> String {
+  let task_id = uuid::Uuid::new_v4().to_string();
+  let mut task = TaskState::new(task_id.clone());
+  task.retry_of = retry_of;
+  let control = Arc::new(TaskControl::default());
+
+  state.tasks.lock().insert(task_id.clone(), task);
+  state.task_inputs.lock().insert(task_id.clone(), input.clone());
+  state
+    .task_controls
+    .lock()
+    .insert(task_id.clone(), control.clone());
+
+  let state_arc = state.clone();
+  persist_task(&state_arc, &task_id);
+  let task_id_for_run = task_id.clone();
+
+  tauri::async_runtime::spawn(async move {
+    let sink: Arc<dyn ProgressSink> = Arc::new(TauriProgressSink(app));
+    downloader::run_task(sink, state_arc, task_id_for_run, input, control).await;
+  });
+
+  task_id
+}
+
+/// 从已有文件重建整合包，作为独立任务运行（可取消、暂停，进度通过 `task_event` 推送）。
+/// 整合任务不写入任务历史。
+#[tauri::command]
+async fn create_bundle(
+  app: tauri::AppHandle,
+  state: tauri::State<'_, AppRuntimeState>,
+  input: BundleTaskInput,
+) -> Result<StartTaskResult, String> {
+  let has_dir = input.source_dir.as_deref().is_some_and(|d| !d.trim().is_empty());
+  let has_files = input.files.as_ref().is_some_and(|f| !f.is_empty());
+  if !has_dir && !has_files {
+    return Err("sourceDir or files is required".to_string());
+  }
+
+  let task_id = uuid::Uuid::new_v4().to_string();
+  let control = Arc::new(TaskControl::default());
+  state.0.tasks.lock().insert(task_id.clone(), TaskState::new(task_id.clone()));
+  state
+    .0
+    .task_controls
+    .lock()
+    .insert(task_id.clone(), control.clone());
+
+  let state_arc = state.0.clone();
+  let task_id_for_run = task_id.clone();
+  tauri::async_runtime::spawn(async move {
+    let sink: Arc<dyn ProgressSink> = Arc::new(TauriProgressSink(app));
+    bundle_task::run_bundle_task(sink, state_arc, task_id_for_run, input, control).await;
+  });
+
+  Ok(StartTaskResult { task_id })
+}
+
+#[tauri::command]
+async fn verify_bundle(bundle_path: String) -> Result<bundle_index::BundleVerifyReport, String> {
+  let path = PathBuf::from(bundle_path.trim());
+  tauri::async_runtime::spawn_blocking(move || bundle_index::verify_bundle(&path))
+    .await
+    .map_err(|e| format!("verify task failed: {}", e))?
+    .map_err(|e| format!("{:#}", e))
+}
+
+/// 重试时可替换的凭据；从历史恢复的任务输入已脱敏，必须重新提供。
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RetryCredentials {
+  connect_sid: String,
+  auth_mode: String,
+  key: Option<String>,
+  captcha: Option<String>,
+}
+
+#[tauri::command]
+async fn retry_failed_items(
+  app: tauri::AppHandle,
+  state: tauri::State<'_, AppRuntimeState>,
+  task_id: String,
+  credentials: Option<RetryCredentials>,
+) -> Result<StartTaskResult, String> {
+  let (status, failed_ids) = {
+    let tasks = state.0.tasks.lock();
+    let task = tasks
+      .get(&task_id)
+      .ok_or_else(|| format!("task not found: {}", task_id))?;
+    let mut ids: Vec<String> = task.fail_items.iter().map(|f| f.id.clone()).collect();
+    ids.sort();
+    ids.dedup();
+    (task.status.clone(), ids)
+  };
+  if matches!(status.as_str(), "pending" | "running" | "paused") {
+    return Err(format!("task is still running: {}", task_id));
+  }
+  if failed_ids.is_empty() {
+    return Err(format!("task has no failed items: {}", task_id));
+  }
+
+  let mut input = state
+    .0
+    .task_inputs
+    .lock()
+    .get(&task_id)
+    .cloned()
+    .ok_or_else(|| format!("task input not found: {}", task_id))?;
+
+  if let Some(c) = credentials {
+    input.connect_sid = c.connect_sid;
+    input.auth_mode = c.auth_mode;
+    input.key = c.key;
+    input.captcha = c.captcha;
+  }
+  if input.connect_sid.trim().is_empty() {
+    return Err("connect.sid is required to retry this task".to_string());
+  }
+
+  input.selected_manifest_paths = Vec::new();
+  input.level_ids = Some(failed_ids);
+  // 指定路径会覆盖原任务的整合包，重试时改为自动生成文件名。
+  input.bundle_output_path = None;
+
+  let new_task_id = spawn_download_task(app, &state.0, input, Some(task_id));
+  Ok(StartTaskResult { task_id: new_task_id })
+}
+
+#[tauri::command]
+async fn cancel_task(
+  task_id: String,
+  state: tauri::State<'_, AppRuntimeState>,
+) -> Result<(), String> {
+  let map = state.0.task_controls.lock();
+  if let Some(control) = map.get(&task_id) {
+    control.cancel();
+    Ok(())
+  } else {
+    Err(format!("task not found: {}", task_id))
+  }
+}
+
+fn task_control(state: &Arc<InnerState>, task_id: &str) -> Result<Arc<TaskControl>, String> {
+  state
+    .task_controls
+    .lock()
+    .get(task_id)
+    .cloned()
+    .ok_or_else(|| format!("task not found: {}", task_id))
+}
+
+#[tauri::command]
+async fn pause_task(
+  app: tauri::AppHandle,
+  task_id: String,
+  state: tauri::State<'_, AppRuntimeState>,
+) -> Result<(), String> {
+  let control = task_control(&state.0, &task_id)?;
+  let line = "任务已暂停（进行中的下载会先完成）".to_string();
+  // 检查与修改在同一次加锁中完成，避免任务恰好结束时把 completed 覆盖为 paused。
+  let paused = update_task(&state.0, &task_id, |t| {
+    if t.status != "running" {
+      return false;
+    }
+    control.pause();
+    t.status = "paused".to_string();
+    t.message = Some(line.clone());
+    true
+  });
+  if paused != Some(true) {
+    return Err(format!("task is not running: {}", task_id));
+  }
+  push_log(&state.0, &task_id, line.clone());
+  persist_task(&state.0, &task_id);
+  downloader::emit_event(&TauriProgressSink(app), &task_id, "warn", "paused", line, Some("paused".to_string()));
+  Ok(())
+}
+
+#[tauri::command]
+async fn resume_task(
+  app: tauri::AppHandle,
+  task_id: String,
+  state: tauri::State<'_, AppRuntimeState>,
+) -> Result<(), String> {
+  let control = task_control(&state.0, &task_id)?;
+  let line = "任务已恢复".to_string();
+  let resumed = update_task(&state.0, &task_id, |t| {
+    if t.status != "paused" {
+      return false;
+    }
+    t.status = "running".to_string();
+    t.message = Some(line.clone());
+    control.resume();
+    true
+  });
+  if resumed != Some(true) {
+    return Err(format!("task is not paused: {}", task_id));
+  }
+  push_log(&state.0, &task_id, line.clone());
+  persist_task(&state.0, &task_id);
+  downloader::emit_event(&TauriProgressSink(app), &task_id, "info", "resumed", line, Some("running".to_string()));
+  Ok(())
+}
+
+#[tauri::command]
+async fn get_task_state(
+  task_id: String,
+  state: tauri::State<'_, AppRuntimeState>,
+) -> Result<Option<TaskState>, String> {
+  Ok(state.0.tasks.lock().get(&task_id).cloned())
+}
+
+#[tauri::command]
+async fn scan_library(
+  output_dir: String,
+  manifest_paths: Vec<String>,
+) -> Result<library::LibraryScanReport, String> {
+  let dir = PathBuf::from(output_dir.trim());
+  tauri::async_runtime::spawn_blocking(move || library::scan_library(&dir, &manifest_paths))
+    .await
+    .map_err(|e| format!("scan task failed: {}", e))?
+    .map_err(|e| format!("{:#}", e))
+}
+
+#[tauri::command]
+async fn build_chart_index(
+  output_dir: String,
+  manifest_paths: Option<Vec<String>>,
+) -> Result<chart_index::ChartIndexSummary, String> {
+  let dir = PathBuf::from(output_dir.trim());
+  let manifest_paths = manifest_paths.unwrap_or_default();
+  tauri::async_runtime::spawn_blocking(move || chart_index::build_index(&dir, &manifest_paths))
+    .await
+    .map_err(|e| format!("index task failed: {}", e))?
+    .map_err(|e| format!("{:#}", e))
+}
+
+#[tauri::command]
+async fn query_charts(
+  output_dir: String,
+  query: chart_index::ChartQuery,
+) -> Result<Vec<chart_index::ChartIndexEntry>, String> {
+  let index = chart_index::load_index(&PathBuf::from(output_dir.trim()));
+  Ok(chart_index::query_index(&index, &query))
+}
+
+#[tauri::command]
+async fn list_tasks(state: tauri::State<'_, AppRuntimeState>) -> Result<Vec<TaskRecord>, String> {
+  let tasks = state.0.tasks.lock();
+  let inputs = state.0.task_inputs.lock();
+  let mut out: Vec<TaskRecord> = tasks
+    .values()
+    .filter_map(|t| {
+      let input = inputs.get(&t.task_id).map(task_store::redact_input)?;
+      let mut task = t.clone();
+      // 列表只用于概览，日志通过 get_task_state 单独获取。
+      task.logs.clear();
+      Some(TaskRecord { input, state: task })
+    })
+    .collect();
+  out.sort_by(|a, b| b.state.started_at.cmp(&a.state.started_at));
+  Ok(out)
+}
+
+#[tauri::command]
+async fn delete_task(
+  task_id: String,
+  state: tauri::State<'_, AppRuntimeState>,
+) -> Result<(), String> {
+  let status = state
+    .0
+    .tasks
+    .lock()
+    .get(&task_id)
+    .map(|t| t.status.clone())
+    .ok_or_else(|| format!("task not found: {}", task_id))?;
+  if matches!(status.as_str(), "pending" | "running" | "paused") {
+    return Err(format!("task is still running: {}", task_id));
+  }
+
+  if let Some(store) = state.0.task_store.lock().as_ref() {
+    store.delete(&task_id).map_err(|e| format!("{:#}", e))?;
+  }
+  state.0.tasks.lock().remove(&task_id);
+  state.0.task_inputs.lock().remove(&task_id);
+  state.0.task_controls.lock().remove(&task_id);
+  Ok(())
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+  tauri::Builder::default()
+    .plugin(tauri_plugin_dialog::init())
+    .manage(AppRuntimeState::default())
+    .invoke_handler(tauri::generate_handler![
+      list_builtin_collections,
+      refresh_collections_from_dir,
+      diff_collections,
+      create_manifest,
+      update_manifest,
+      combine_manifests,
+      export_collections,
+      start_download_task,
+      retry_failed_items,
+      create_bundle,
+      verify_bundle,
+      cancel_task,
+      pause_task,
+      resume_task,
+      get_task_state,
+      scan_library,
+      build_chart_index,
+      query_charts,
+      list_tasks,
+      delete_task
+    ])
+    .setup(|app| {
+      let _ = app.get_webview_window("main");
+      match app.path().app_data_dir() {
+        Ok(dir) => restore_task_history(&app.state::<AppRuntimeState>().0, dir.join("tasks")),
+        Err(e) => eprintln!("resolve app data dir failed: {}", e),
+      }
+      Ok(())
+    })
+    .run(tauri::generate_context!())
+    .expect("error while running tauri application");
+}
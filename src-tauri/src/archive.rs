@@ -0,0 +1,90 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use zip::ZipArchive;
+
+/// 谱面包校验失败；下载流程据此把失败归类为 `verify_fail`。
+#[derive(Debug)]
+pub struct VerifyError(pub String);
+
+impl fmt::Display for VerifyError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+
+impl std::error::Error for VerifyError {}
+
+#[derive(Debug, Clone)]
+pub struct ArchiveCheck {
+  /// 含 maidata.txt 的谱面文件夹（压缩包内路径，不带结尾 `/`）。
+  pub chart_folders: Vec<String>,
+  pub entry_count: usize,
+  /// 任一谱面文件夹带有 BGA 视频。
+  pub has_bga: bool,
+}
+
+const VIDEO_EXTENSIONS: [&str; 5] = ["mp4", "avi", "mov", "webm", "mkv"];
+
+/// 谱面文件夹内是否带有 BGA 视频（pv.mp4 等）。
+pub fn folder_has_video<'a>(entry_names: impl IntoIterator<Item = &'a str>, folder: &str) -> bool {
+  let prefix = format!("{}/", folder);
+  entry_names.into_iter().any(|name| {
+    name.starts_with(&prefix)
+      && name
+        .rsplit_once('.')
+        .is_some_and(|(_, ext)| VIDEO_EXTENSIONS.iter().any(|v| ext.eq_ignore_ascii_case(v)))
+  })
+}
+
+/// 返回 maidata.txt 所在的文件夹；不在任何文件夹内的 maidata.txt 不算谱面。
+pub fn chart_folder_of(entry_name: &str) -> Option<&str> {
+  let (folder, file) = entry_name.rsplit_once('/')?;
+  if folder.is_empty() || !file.eq_ignore_ascii_case("maidata.txt") {
+    return None;
+  }
+  Some(folder)
+}
+
+/// 校验谱面包：能以 zip 打开，且至少包含一个 `<folder>/maidata.txt`。
+/// `deep` 为 true 时完整解压一遍以校验每个条目的 CRC。
+pub fn verify_chart_archive(path: &Path, deep: bool) -> Result<ArchiveCheck, VerifyError> {
+  let file = File::open(path).map_err(|e| VerifyError(format!("open archive failed {}: {}", path.display(), e)))?;
+  let mut archive =
+    ZipArchive::new(file).map_err(|e| VerifyError(format!("not a valid zip archive {}: {}", path.display(), e)))?;
+
+  let mut chart_folders: Vec<String> = archive
+    .file_names()
+    .filter_map(chart_folder_of)
+    .map(|s| s.to_string())
+    .collect();
+  chart_folders.sort();
+  chart_folders.dedup();
+
+  if chart_folders.is_empty() {
+    return Err(VerifyError(format!("no chart folder with maidata.txt in {}", path.display())));
+  }
+
+  let has_bga = chart_folders
+    .iter()
+    .any(|folder| folder_has_video(archive.file_names(), folder));
+
+  if deep {
+    for i in 0..archive.len() {
+      let mut entry = archive
+        .by_index(i)
+        .map_err(|e| VerifyError(format!("read zip entry #{} failed: {}", i, e)))?;
+      let name = entry.name().to_string();
+      io::copy(&mut entry, &mut io::sink())
+        .map_err(|e| VerifyError(format!("zip entry {} is corrupt: {}", name, e)))?;
+    }
+  }
+
+  Ok(ArchiveCheck {
+    chart_folders,
+    entry_count: archive.len(),
+    has_bga,
+  })
+}
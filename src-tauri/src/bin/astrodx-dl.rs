@@ -0,0 +1,395 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use niconico_app_lib::downloader::{self, ProgressSink};
+use niconico_app_lib::models::{BundleTaskInput, DownloadTaskInput, TaskEvent, TaskState};
+use niconico_app_lib::{bundle_index, bundle_task, bundler, collections, filter, InnerState, TaskControl};
+
+const USAGE: &str = "\
+astrodx-dl - AstroDX 谱面批量下载（命令行版）
+
+用法:
+  astrodx-dl download [选项]
+  astrodx-dl bundle [选项]
+  astrodx-dl verify <bundle.adx>...
+
+download 选项:
+  --manifest <path>     manifest.json 或包含它的目录，可重复
+  --id <levelId>        直接指定 level ID，可重复
+  --out <dir>           输出目录（必填）
+  --sid <value>         connect.sid，也可通过环境变量 NICONICO_CONNECT_SID 提供
+  --key <value>         鉴权 key，也可通过环境变量 NICONICO_KEY 提供
+  --captcha <code>      使用验证码换取 key（与 --key 二选一）
+  --format <adx|zip>    输出格式，默认 adx
+  --nobga               下载不包含 BGA 的版本
+  --upgrade-bga         已有文件是无 BGA 版本时重新下载 BGA 版本并替换
+  --bundle              下载完成后自动整合本次新增文件
+  --bundle-out <path>   整合包输出路径
+  --bundle-append       --bundle-out 已存在时只追加其中没有的谱面
+  --bundle-conflict <skip|rename|keep_newest>
+                        整合时同名条目的处理方式，默认 rename
+  --bundle-max-mb <n>   分包：每个整合包的大致上限（MB），输出为 *_part01.adx 等
+  --bundle-max-charts <n>
+                        分包：每个整合包最多包含的谱面数
+  --retries <n>         单个谱面最大重试次数，默认 3
+  --interval-ms <n>     获取下载链接的最小间隔，默认 1000
+  --concurrency <n>     并发下载数，默认 3
+  --api-base <url>      下载站 API 地址（镜像），也可通过环境变量 NICONICO_API_BASE 提供
+  --filter <expr>       按谱面元数据筛选，如 \"remaster level>=13 !utage\"
+  --metadata-url <url>  过滤用的元数据接口（GET <url>?id=<levelId>）
+
+bundle 选项（用已下载的文件重建整合包）:
+  --dir <dir>           谱面所在目录；未给 --manifest 时整合其中全部 .adx/.zip
+                        （已生成的整合包除外）
+  --manifest <path>     只整合清单中的 ID（在 --dir 下查找），可重复
+  --file <path>         额外加入的谱面文件，可重复
+  --out <path>          整合包输出路径，默认 <dir>/bundle_merged_<时间>.adx
+  --append              --out 已存在时只追加其中没有的谱面，不重新生成
+  --conflict <skip|rename|keep_newest>
+                        同名条目的处理方式，默认 rename
+  --max-mb <n>          分包：每个整合包的大致上限（MB）
+  --max-charts <n>      分包：每个整合包最多包含的谱面数
+  --per-manifest        每个清单各生成一个 <清单文件夹>.adx，并附带过滤后的
+                        collections/<清单文件夹>/manifest.json；此时 --out 为输出目录
+
+verify：按整合包内的 bundle_index.json 逐个条目校验大小与 SHA-256。
+
+退出码: 0 全部成功；1 存在失败项（verify：存在缺失或损坏的谱面）；2 参数错误或任务失败。";
+
+/// 打印到终端，并在每条结果前附上当前进度（`bundle_progress` 自带进度，不再重复）。
+struct ConsoleSink {
+  state: Arc<InnerState>,
+}
+
+impl ProgressSink for ConsoleSink {
+  fn emit(&self, event: TaskEvent) {
+    let progress = if event.event == "bundle_progress" {
+      None
+    } else {
+      self
+        .state
+        .tasks
+        .lock()
+        .get(&event.task_id)
+        .map(|t| format!("[{}/{}] ", t.processed_ids, t.total_ids))
+    };
+    let line = format!("{}{}", progress.unwrap_or_default(), event.message);
+    if event.level == "error" {
+      eprintln!("{}", line);
+    } else {
+      println!("{}", line);
+    }
+  }
+}
+
+fn take_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+  args.next().ok_or_else(|| format!("{} 需要一个参数", flag))
+}
+
+fn parse_number<T: std::str::FromStr>(value: String, flag: &str) -> Result<T, String> {
+  value.parse().map_err(|_| format!("{} 参数不是有效数字: {}", flag, value))
+}
+
+/// MB 换算为字节；数值过大时报参数错误而不是溢出。
+fn parse_megabytes(value: String, flag: &str) -> Result<u64, String> {
+  let mb: u64 = parse_number(value, flag)?;
+  mb.checked_mul(1024 * 1024).ok_or_else(|| format!("{} 参数过大: {}", flag, mb))
+}
+
+fn expand_manifest_arg(value: &str) -> Result<Vec<String>, String> {
+  let path = PathBuf::from(value);
+  if path.is_dir() {
+    let files = collections::collect_manifest_files(&path);
+    if files.is_empty() {
+      return Err(format!("目录中没有 manifest.json: {}", path.display()));
+    }
+    Ok(files.into_iter().map(|p| p.to_string_lossy().to_string()).collect())
+  } else {
+    Ok(vec![value.to_string()])
+  }
+}
+
+fn parse_download_args(args: Vec<String>) -> Result<DownloadTaskInput, String> {
+  let mut manifests = Vec::new();
+  let mut ids = Vec::new();
+  let mut output_dir = None;
+  let mut connect_sid = std::env::var("NICONICO_CONNECT_SID").ok();
+  let mut key = std::env::var("NICONICO_KEY").ok();
+  let mut captcha = None;
+  let mut output_format = "adx".to_string();
+  let mut download_no_bga = false;
+  let mut upgrade_bga = false;
+  let mut auto_bundle = false;
+  let mut bundle_output_path = None;
+  let mut bundle_conflict_policy = None;
+  let mut bundle_max_bytes = None;
+  let mut bundle_max_charts = None;
+  let mut bundle_append = false;
+  let mut retries = None;
+  let mut request_interval_ms = None;
+  let mut concurrency = None;
+  let mut api_base = None;
+  let mut filter = None;
+  let mut metadata_url = None;
+
+  let mut iter = args.into_iter();
+  while let Some(arg) = iter.next() {
+    match arg.as_str() {
+      "--manifest" => manifests.extend(expand_manifest_arg(&take_value(&mut iter, &arg)?)?),
+      "--id" => ids.push(take_value(&mut iter, &arg)?),
+      "--out" => output_dir = Some(take_value(&mut iter, &arg)?),
+      "--sid" => connect_sid = Some(take_value(&mut iter, &arg)?),
+      "--key" => key = Some(take_value(&mut iter, &arg)?),
+      "--captcha" => captcha = Some(take_value(&mut iter, &arg)?),
+      "--format" => output_format = take_value(&mut iter, &arg)?,
+      "--nobga" => download_no_bga = true,
+      "--upgrade-bga" => upgrade_bga = true,
+      "--bundle" => auto_bundle = true,
+      "--bundle-out" => bundle_output_path = Some(take_value(&mut iter, &arg)?),
+      "--bundle-conflict" => {
+        let value = take_value(&mut iter, &arg)?;
+        bundler::ConflictPolicy::parse(&value).map_err(|e| format!("--bundle-conflict 无效: {}", e))?;
+        bundle_conflict_policy = Some(value);
+      }
+      "--bundle-max-mb" => {
+        bundle_max_bytes = Some(parse_megabytes(take_value(&mut iter, &arg)?, &arg)?);
+      }
+      "--bundle-append" => bundle_append = true,
+      "--bundle-max-charts" => bundle_max_charts = Some(parse_number(take_value(&mut iter, &arg)?, &arg)?),
+      "--retries" => retries = Some(parse_number(take_value(&mut iter, &arg)?, &arg)?),
+      "--interval-ms" => request_interval_ms = Some(parse_number(take_value(&mut iter, &arg)?, &arg)?),
+      "--concurrency" => concurrency = Some(parse_number(take_value(&mut iter, &arg)?, &arg)?),
+      "--api-base" => api_base = Some(take_value(&mut iter, &arg)?),
+      "--filter" => filter = Some(take_value(&mut iter, &arg)?),
+      "--metadata-url" => metadata_url = Some(take_value(&mut iter, &arg)?),
+      other => return Err(format!("未知参数: {}", other)),
+    }
+  }
+
+  if manifests.is_empty() && ids.is_empty() {
+    return Err("至少需要一个 --manifest 或 --id".to_string());
+  }
+  let output_dir = output_dir.ok_or("缺少 --out")?;
+  let connect_sid = connect_sid
+    .filter(|s| !s.trim().is_empty())
+    .ok_or("缺少 --sid（或环境变量 NICONICO_CONNECT_SID）")?;
+  if !matches!(output_format.as_str(), "adx" | "zip") {
+    return Err(format!("--format 只支持 adx 或 zip: {}", output_format));
+  }
+
+  if upgrade_bga && download_no_bga {
+    return Err("--upgrade-bga 不能与 --nobga 同时使用".to_string());
+  }
+
+  if let Some(expr) = &filter {
+    filter::parse_filter(expr).map_err(|e| format!("--filter 无效: {}", e))?;
+  }
+
+  let auth_mode = if captcha.is_some() { "captcha" } else { "key" };
+  if auth_mode == "key" && key.as_deref().is_none_or(|k| k.trim().is_empty()) {
+    return Err("缺少 --key（或环境变量 NICONICO_KEY），也可改用 --captcha".to_string());
+  }
+
+  Ok(DownloadTaskInput {
+    selected_manifest_paths: manifests,
+    output_dir,
+    connect_sid,
+    auth_mode: auth_mode.to_string(),
+    key,
+    captcha,
+    download_no_bga,
+    output_format,
+    auto_bundle,
+    bundle_output_path,
+    bundle_conflict_policy,
+    bundle_max_bytes,
+    bundle_max_charts,
+    bundle_append,
+    upgrade_bga,
+    retries,
+    request_interval_ms,
+    concurrency,
+    level_ids: if ids.is_empty() { None } else { Some(ids) },
+    api_base,
+    filter,
+    metadata_url,
+  })
+}
+
+fn parse_bundle_args(args: Vec<String>) -> Result<BundleTaskInput, String> {
+  let mut input = BundleTaskInput::default();
+  let mut manifests = Vec::new();
+  let mut files = Vec::new();
+
+  let mut iter = args.into_iter();
+  while let Some(arg) = iter.next() {
+    match arg.as_str() {
+      "--dir" => input.source_dir = Some(take_value(&mut iter, &arg)?),
+      "--manifest" => manifests.extend(expand_manifest_arg(&take_value(&mut iter, &arg)?)?),
+      "--file" => files.push(take_value(&mut iter, &arg)?),
+      "--out" => input.output_path = Some(take_value(&mut iter, &arg)?),
+      "--conflict" => {
+        let value = take_value(&mut iter, &arg)?;
+        bundler::ConflictPolicy::parse(&value).map_err(|e| format!("--conflict 无效: {}", e))?;
+        input.conflict_policy = Some(value);
+      }
+      "--max-mb" => {
+        input.max_bytes = Some(parse_megabytes(take_value(&mut iter, &arg)?, &arg)?);
+      }
+      "--max-charts" => input.max_charts = Some(parse_number(take_value(&mut iter, &arg)?, &arg)?),
+      "--per-manifest" => input.per_manifest = true,
+      "--append" => input.append = true,
+      other => return Err(format!("未知参数: {}", other)),
+    }
+  }
+
+  if input.source_dir.is_none() && files.is_empty() {
+    return Err("至少需要 --dir 或 --file".to_string());
+  }
+  if !manifests.is_empty() && input.source_dir.is_none() {
+    return Err("--manifest 需要配合 --dir 使用".to_string());
+  }
+  if input.per_manifest && manifests.is_empty() {
+    return Err("--per-manifest 需要至少一个 --manifest".to_string());
+  }
+  input.manifest_paths = Some(manifests);
+  input.files = Some(files);
+  Ok(input)
+}
+
+fn print_summary(task: &TaskState) {
+  println!(
+    "\n状态: {} | 总数 {} | 成功 {} | 跳过 {} | 失败 {}",
+    task.status, task.total_ids, task.ok_count, task.skip_count, task.fail_count
+  );
+  for path in &task.bundle_output_paths {
+    println!("整合包: {}", path);
+  }
+  for item in &task.fail_items {
+    eprintln!("  FAIL {}: {}", item.id, item.reason);
+  }
+}
+
+async fn run_download(args: Vec<String>) -> ExitCode {
+  let input = match parse_download_args(args) {
+    Ok(v) => v,
+    Err(e) => {
+      eprintln!("{}\n\n{}", e, USAGE);
+      return ExitCode::from(2);
+    }
+  };
+
+  let state = Arc::new(InnerState::default());
+  let task_id = uuid::Uuid::new_v4().to_string();
+  state
+    .tasks
+    .lock()
+    .insert(task_id.clone(), TaskState::new(task_id.clone()));
+
+  let sink: Arc<dyn ProgressSink> = Arc::new(ConsoleSink { state: state.clone() });
+  let control = Arc::new(TaskControl::default());
+  downloader::run_task(sink, state.clone(), task_id.clone(), input, control).await;
+
+  let Some(task) = state.tasks.lock().get(&task_id).cloned() else {
+    return ExitCode::from(2);
+  };
+  print_summary(&task);
+
+  if task.status != "completed" {
+    ExitCode::from(2)
+  } else if task.fail_count > 0 {
+    ExitCode::from(1)
+  } else {
+    ExitCode::SUCCESS
+  }
+}
+
+async fn run_bundle(args: Vec<String>) -> ExitCode {
+  let input = match parse_bundle_args(args) {
+    Ok(v) => v,
+    Err(e) => {
+      eprintln!("{}\n\n{}", e, USAGE);
+      return ExitCode::from(2);
+    }
+  };
+
+  let state = Arc::new(InnerState::default());
+  let task_id = uuid::Uuid::new_v4().to_string();
+  state
+    .tasks
+    .lock()
+    .insert(task_id.clone(), TaskState::new(task_id.clone()));
+
+  let sink: Arc<dyn ProgressSink> = Arc::new(ConsoleSink { state: state.clone() });
+  let control = Arc::new(TaskControl::default());
+  bundle_task::run_bundle_task(sink, state.clone(), task_id.clone(), input, control).await;
+
+  let Some(task) = state.tasks.lock().get(&task_id).cloned() else {
+    return ExitCode::from(2);
+  };
+  for path in &task.bundle_output_paths {
+    println!("整合包: {}", path);
+  }
+  if task.status == "completed" {
+    ExitCode::SUCCESS
+  } else {
+    ExitCode::from(2)
+  }
+}
+
+fn run_verify(paths: Vec<String>) -> ExitCode {
+  if paths.is_empty() {
+    eprintln!("至少需要一个整合包路径\n\n{}", USAGE);
+    return ExitCode::from(2);
+  }
+
+  let mut code = ExitCode::SUCCESS;
+  for path in &paths {
+    let report = match bundle_index::verify_bundle(&PathBuf::from(path)) {
+      Ok(v) => v,
+      Err(e) => {
+        eprintln!("{}: 校验失败: {:#}", path, e);
+        return ExitCode::from(2);
+      }
+    };
+    println!(
+      "{}: 谱面 {} | 正常 {} | 缺失 {} | 损坏 {}",
+      path, report.chart_count, report.ok_count, report.missing_count, report.corrupt_count
+    );
+    for chart in &report.problems {
+      eprintln!(
+        "  {} {} ({}): {}",
+        chart.status.to_uppercase(),
+        chart.level_id,
+        chart.source_file,
+        chart.problems.join("; ")
+      );
+    }
+    for name in &report.unindexed_entries {
+      eprintln!("  UNINDEXED {}", name);
+    }
+    if !report.problems.is_empty() {
+      code = ExitCode::from(1);
+    }
+  }
+  code
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+  let mut args = std::env::args().skip(1);
+  match args.next().as_deref() {
+    Some("download") => run_download(args.collect()).await,
+    Some("bundle") => run_bundle(args.collect()).await,
+    Some("verify") => run_verify(args.collect()),
+    None | Some("-h") | Some("--help") => {
+      println!("{}", USAGE);
+      ExitCode::SUCCESS
+    }
+    Some(other) => {
+      eprintln!("未知命令: {}\n\n{}", other, USAGE);
+      ExitCode::from(2)
+    }
+  }
+}
@@ -0,0 +1,213 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zip::ZipArchive;
+
+/// 整合包根目录下的内容清单。
+pub const BUNDLE_INDEX_NAME: &str = "bundle_index.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleIndexEntry {
+  /// 包内路径。
+  pub name: String,
+  /// 解压后大小。
+  pub size: u64,
+  /// 解压后内容的 SHA-256（小写十六进制）。
+  pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleIndexChart {
+  pub level_id: String,
+  pub source_file: String,
+  /// 含 maidata.txt 的谱面文件夹（包内路径）。
+  pub folders: Vec<String>,
+  pub entries: Vec<BundleIndexEntry>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleIndex {
+  pub version: u32,
+  pub created_at: String,
+  pub charts: Vec<BundleIndexChart>,
+}
+
+impl BundleIndex {
+  pub fn new(charts: Vec<BundleIndexChart>) -> Self {
+    Self {
+      version: 1,
+      created_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+      charts,
+    }
+  }
+}
+
+/// 解压并计算一个条目的大小与 SHA-256；CRC 不符时返回错误。
+pub fn hash_entry(reader: &mut impl Read) -> io::Result<(u64, String)> {
+  let mut hasher = Sha256::new();
+  let size = io::copy(reader, &mut hasher)?;
+  Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+/// 读取包内的 `bundle_index.json`；没有时返回 None。
+pub fn read_index<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Option<BundleIndex>> {
+  let entry = match archive.by_name(BUNDLE_INDEX_NAME) {
+    Ok(v) => v,
+    Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+    Err(e) => return Err(e.into()),
+  };
+  let index = serde_json::from_reader(entry).context("parse bundle index failed")?;
+  Ok(Some(index))
+}
+
+/// 是否为整合包（根目录带有 `bundle_index.json`）；无法以 zip 打开时视为不是。
+pub fn is_bundle(path: &Path) -> bool {
+  File::open(path)
+    .ok()
+    .and_then(|file| ZipArchive::new(BufReader::new(file)).ok())
+    .is_some_and(|archive| archive.file_names().any(|n| n == BUNDLE_INDEX_NAME))
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleChartCheck {
+  pub level_id: String,
+  pub source_file: String,
+  /// ok / missing（有条目不在包内）/ corrupt（大小、哈希或 CRC 不符）
+  pub status: String,
+  pub problems: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleVerifyReport {
+  pub bundle_path: String,
+  pub chart_count: usize,
+  pub ok_count: usize,
+  pub missing_count: usize,
+  pub corrupt_count: usize,
+  /// 有问题的谱面；全部正常时为空。
+  pub problems: Vec<BundleChartCheck>,
+  /// 包内存在但未记录在索引中的条目（`collections/` 下的清单除外）。
+  pub unindexed_entries: Vec<String>,
+}
+
+fn check_chart<R: Read + Seek>(archive: &mut ZipArchive<R>, chart: &BundleIndexChart) -> BundleChartCheck {
+  let mut missing = false;
+  let mut problems = Vec::new();
+  for expected in &chart.entries {
+    let mut entry = match archive.by_name(&expected.name) {
+      Ok(v) => v,
+      Err(_) => {
+        missing = true;
+        problems.push(format!("missing entry: {}", expected.name));
+        continue;
+      }
+    };
+    match hash_entry(&mut entry) {
+      Ok((size, _)) if size != expected.size => problems.push(format!(
+        "size mismatch: {} ({} != {})",
+        expected.name, size, expected.size
+      )),
+      Ok((_, sha256)) if sha256 != expected.sha256 => problems.push(format!("sha256 mismatch: {}", expected.name)),
+      Ok(_) => {}
+      Err(e) => problems.push(format!("read failed: {}: {}", expected.name, e)),
+    }
+  }
+
+  let status = if missing {
+    "missing"
+  } else if !problems.is_empty() {
+    "corrupt"
+  } else {
+    "ok"
+  };
+  BundleChartCheck {
+    level_id: chart.level_id.clone(),
+    source_file: chart.source_file.clone(),
+    status: status.to_string(),
+    problems,
+  }
+}
+
+/// 重新打开整合包，按 `bundle_index.json` 逐个条目解压校验。
+pub fn verify_bundle(path: &Path) -> Result<BundleVerifyReport> {
+  let file = File::open(path).with_context(|| format!("open bundle failed: {}", path.display()))?;
+  let mut archive =
+    ZipArchive::new(BufReader::new(file)).with_context(|| format!("read zip archive failed: {}", path.display()))?;
+  let index =
+    read_index(&mut archive)?.ok_or_else(|| anyhow!("{} not found in {}", BUNDLE_INDEX_NAME, path.display()))?;
+
+  let checks: Vec<BundleChartCheck> = index.charts.iter().map(|c| check_chart(&mut archive, c)).collect();
+
+  let indexed: HashSet<&str> = index
+    .charts
+    .iter()
+    .flat_map(|c| c.entries.iter().map(|e| e.name.as_str()))
+    .collect();
+  let mut unindexed_entries: Vec<String> = archive
+    .file_names()
+    .filter(|n| *n != BUNDLE_INDEX_NAME && !n.starts_with("collections/") && !n.ends_with('/'))
+    .filter(|n| !indexed.contains(n))
+    .map(str::to_string)
+    .collect();
+  unindexed_entries.sort();
+
+  let count = |status: &str| checks.iter().filter(|c| c.status == status).count();
+  Ok(BundleVerifyReport {
+    bundle_path: path.to_string_lossy().to_string(),
+    chart_count: checks.len(),
+    ok_count: count("ok"),
+    missing_count: count("missing"),
+    corrupt_count: count("corrupt"),
+    problems: checks.into_iter().filter(|c| c.status != "ok").collect(),
+    unindexed_entries,
+  })
+}
+
+/// 为没有索引的旧整合包补建索引：按顶层文件夹（即源文件名）分组，level ID 取文件夹名。
+pub(crate) fn index_by_root<R: Read + Seek>(
+  archive: &mut ZipArchive<R>,
+  source_file: &str,
+) -> Result<Vec<BundleIndexChart>> {
+  let mut charts: Vec<BundleIndexChart> = Vec::new();
+  for index in 0..archive.len() {
+    let mut entry = archive.by_index(index)?;
+    let name = entry.name().to_string();
+    if entry.is_dir() || name == BUNDLE_INDEX_NAME || name.starts_with("collections/") {
+      continue;
+    }
+    let Some((root, _)) = name.split_once('/') else {
+      continue;
+    };
+    let root = root.to_string();
+    let (size, sha256) = hash_entry(&mut entry).with_context(|| format!("hash entry failed: {}", name))?;
+
+    let pos = match charts.iter().position(|c| c.level_id == root) {
+      Some(pos) => pos,
+      None => {
+        charts.push(BundleIndexChart {
+          level_id: root,
+          source_file: source_file.to_string(),
+          folders: Vec::new(),
+          entries: Vec::new(),
+        });
+        charts.len() - 1
+      }
+    };
+    let chart = &mut charts[pos];
+    if let Some(folder) = crate::archive::chart_folder_of(&name) {
+      chart.folders.push(folder.to_string());
+    }
+    chart.entries.push(BundleIndexEntry { name, size, sha256 });
+  }
+  Ok(charts)
+}
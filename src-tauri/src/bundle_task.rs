@@ -0,0 +1,324 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Local;
+
+use crate::bundler::{self, BundleCancelled, BundleCollection, BundleOptions, BundleProgress};
+use crate::collections;
+use crate::downloader::{emit_event, now_str, sanitize_id_for_filename, ProgressSink};
+use crate::fsutil;
+use crate::library;
+use crate::models::BundleTaskInput;
+use crate::{push_log, set_task_message, update_task, InnerState, TaskControl};
+
+/// 把整合进度写回任务状态；暂停时在谱面之间等待。
+struct TaskBundleProgress {
+  sink: Arc<dyn ProgressSink>,
+  state: Arc<InnerState>,
+  task_id: String,
+  control: Arc<TaskControl>,
+  /// 多个整合包依次生成时，之前的整合包已处理的源文件数与全部源文件数。
+  base: usize,
+  total: usize,
+}
+
+impl BundleProgress for TaskBundleProgress {
+  fn chart_done(&self, done: usize, _total: usize, source: &Path) {
+    let done = self.base + done;
+    let total = self.total;
+    update_task(&self.state, &self.task_id, |t| {
+      t.processed_ids = done;
+      t.bundled_count += 1;
+    });
+    let name = source.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    emit_event(
+      &self.sink,
+      &self.task_id,
+      "info",
+      "bundle_progress",
+      format!("[{}/{}] {}", done, total, name),
+      Some("running".to_string()),
+    );
+  }
+
+  fn wait_while_paused(&self) {
+    while self.control.is_paused() && !self.control.is_cancelled() {
+      std::thread::sleep(Duration::from_millis(200));
+    }
+  }
+
+  fn is_cancelled(&self) -> bool {
+    self.control.is_cancelled()
+  }
+}
+
+fn default_output_path(input: &BundleTaskInput, files: &[PathBuf]) -> PathBuf {
+  let dir = input
+    .source_dir
+    .as_deref()
+    .map(PathBuf::from)
+    .or_else(|| files.first().and_then(|f| f.parent()).map(Path::to_path_buf))
+    .unwrap_or_default();
+  dir.join(format!("bundle_merged_{}.adx", Local::now().format("%Y%m%d_%H%M%S")))
+}
+
+fn non_empty_path(value: Option<&str>) -> Option<PathBuf> {
+  value.map(str::trim).filter(|s| !s.is_empty()).map(PathBuf::from)
+}
+
+/// 生成（或追加）一个整合包。
+struct BundleJob {
+  files: Vec<PathBuf>,
+  output_path: PathBuf,
+  options: BundleOptions,
+  /// 追加到已存在的 `output_path`。
+  append: bool,
+}
+
+#[derive(Default)]
+struct BundlePlan {
+  jobs: Vec<BundleJob>,
+  missing_ids: Vec<String>,
+  /// 本地一个文件都没有、因此不生成整合包的清单。
+  empty_manifests: Vec<String>,
+  /// 文件夹名清理后与其他清单重名、整合包改名的清单：(文件夹, 整合包文件名)。
+  renamed_outputs: Vec<(String, String)>,
+}
+
+/// `<folder>.adx`，与已用的文件名（不区分大小写）重复时依次改为 `<folder> (2).adx`……
+fn unique_bundle_name(folder: &str, used: &mut HashSet<String>) -> String {
+  let stem = sanitize_id_for_filename(folder);
+  let mut name = format!("{}.adx", stem);
+  let mut n = 2;
+  while !used.insert(name.to_lowercase()) {
+    name = format!("{} ({}).adx", stem, n);
+    n += 1;
+  }
+  name
+}
+
+fn plan_single(input: &BundleTaskInput, options: &BundleOptions) -> anyhow::Result<BundlePlan> {
+  let source_dir = non_empty_path(input.source_dir.as_deref());
+  let mut resolved = library::resolve_chart_files(
+    source_dir.as_deref(),
+    input.files.as_deref().unwrap_or_default(),
+    input.manifest_paths.as_deref().unwrap_or_default(),
+  )?;
+  let output_path =
+    non_empty_path(input.output_path.as_deref()).unwrap_or_else(|| default_output_path(input, &resolved.files));
+  // 输出在来源目录中时，不能把输出包自身当作来源。
+  resolved.files.retain(|f| !fsutil::same_file(f, &output_path));
+
+  let mut plan = BundlePlan {
+    missing_ids: resolved.missing_ids,
+    ..BundlePlan::default()
+  };
+  if !resolved.files.is_empty() {
+    plan.jobs.push(BundleJob {
+      files: resolved.files,
+      append: input.append && output_path.is_file(),
+      output_path,
+      options: BundleOptions {
+        level_ids: resolved.level_ids,
+        ..options.clone()
+      },
+    });
+  }
+  Ok(plan)
+}
+
+/// 每个清单一个整合包 `<输出目录>/<清单文件夹>.adx`，包内附带过滤后的清单。
+fn plan_per_manifest(input: &BundleTaskInput, options: &BundleOptions) -> anyhow::Result<BundlePlan> {
+  let source_dir = non_empty_path(input.source_dir.as_deref())
+    .ok_or_else(|| anyhow::anyhow!("sourceDir is required for per-manifest bundles"))?;
+  let manifests = input.manifest_paths.as_deref().unwrap_or_default();
+  if manifests.is_empty() {
+    return Err(anyhow::anyhow!("manifestPaths is required for per-manifest bundles"));
+  }
+  if input.append {
+    return Err(anyhow::anyhow!("append is not supported for per-manifest bundles"));
+  }
+  let output_dir = non_empty_path(input.output_path.as_deref()).unwrap_or_else(|| source_dir.clone());
+
+  let mut plan = BundlePlan::default();
+  let mut used_names = HashSet::new();
+  for p in manifests {
+    let path = Path::new(p);
+    let parsed = collections::parse_manifest_file(path).map_err(|e| anyhow::anyhow!(e))?;
+    let folder = path
+      .parent()
+      .and_then(|d| d.file_name())
+      .map(|s| s.to_string_lossy().to_string())
+      .unwrap_or_else(|| parsed.name.clone());
+
+    let mut collection = BundleCollection {
+      folder: folder.clone(),
+      name: parsed.name,
+      level_ids: HashMap::new(),
+    };
+    let mut files = Vec::new();
+    let mut seen = HashSet::new();
+    for id in parsed.level_ids {
+      if !seen.insert(id.clone()) {
+        continue;
+      }
+      match library::local_chart_file(&source_dir, &id) {
+        Some(file) => {
+          collection.level_ids.insert(file.clone(), id);
+          files.push(file);
+        }
+        None => plan.missing_ids.push(id),
+      }
+    }
+
+    if files.is_empty() {
+      plan.empty_manifests.push(folder);
+      continue;
+    }
+    let file_name = unique_bundle_name(&folder, &mut used_names);
+    if file_name != format!("{}.adx", sanitize_id_for_filename(&folder)) {
+      plan.renamed_outputs.push((folder.clone(), file_name.clone()));
+    }
+    plan.jobs.push(BundleJob {
+      files,
+      output_path: output_dir.join(file_name),
+      options: BundleOptions {
+        collection: Some(collection),
+        ..options.clone()
+      },
+      append: false,
+    });
+  }
+  Ok(plan)
+}
+
+pub async fn run_bundle_task(
+  sink: Arc<dyn ProgressSink>,
+  state: Arc<InnerState>,
+  task_id: String,
+  input: BundleTaskInput,
+  control: Arc<TaskControl>,
+) {
+  let fail = |message: String| {
+    set_task_message(&state, &task_id, "failed", message.clone());
+    emit_event(&sink, &task_id, "error", "fatal", message, Some("failed".to_string()));
+  };
+
+  let options = match BundleOptions::from_params(input.conflict_policy.as_deref(), input.max_bytes, input.max_charts) {
+    Ok(v) => v,
+    Err(e) => return fail(format!("整合参数无效: {}", e)),
+  };
+
+  update_task(&state, &task_id, |t| {
+    t.status = "running".to_string();
+    t.started_at = Some(now_str());
+    t.message = Some("整合任务启动".to_string());
+  });
+  emit_event(&sink, &task_id, "info", "start", format!("整合任务启动: {}", task_id), Some("running".to_string()));
+
+  let plan_input = input.clone();
+  let plan = tokio::task::spawn_blocking(move || {
+    if plan_input.per_manifest {
+      plan_per_manifest(&plan_input, &options)
+    } else {
+      plan_single(&plan_input, &options)
+    }
+  })
+  .await;
+  let plan = match plan {
+    Ok(Ok(v)) => v,
+    Ok(Err(e)) => return fail(format!("解析整合来源失败: {:#}", e)),
+    Err(e) => return fail(format!("解析整合来源异常: {}", e)),
+  };
+
+  if !plan.missing_ids.is_empty() {
+    let line = format!(
+      "清单中 {} 个 ID 在本地没有文件，已跳过: {}",
+      plan.missing_ids.len(),
+      plan.missing_ids.join(", ")
+    );
+    push_log(&state, &task_id, line.clone());
+    emit_event(&sink, &task_id, "warn", "bundle_missing", line, Some("running".to_string()));
+  }
+  if !plan.empty_manifests.is_empty() {
+    let line = format!("以下清单在本地没有任何谱面，未生成整合包: {}", plan.empty_manifests.join(", "));
+    push_log(&state, &task_id, line.clone());
+    emit_event(&sink, &task_id, "warn", "bundle_missing", line, Some("running".to_string()));
+  }
+  for (folder, file_name) in &plan.renamed_outputs {
+    let line = format!("清单 {} 的整合包与其他清单重名，改为 {}", folder, file_name);
+    push_log(&state, &task_id, line.clone());
+    emit_event(&sink, &task_id, "warn", "bundle_conflict", line, Some("running".to_string()));
+  }
+  if plan.jobs.is_empty() {
+    return fail("没有可整合的谱面文件".to_string());
+  }
+
+  let total: usize = plan.jobs.iter().map(|j| j.files.len()).sum();
+  update_task(&state, &task_id, |t| {
+    t.total_ids = total;
+    t.message = Some(format!("待整合文件数: {}", total));
+  });
+
+  let mut base = 0;
+  for job in plan.jobs {
+    let progress = TaskBundleProgress {
+      sink: sink.clone(),
+      state: state.clone(),
+      task_id: task_id.clone(),
+      control: control.clone(),
+      base,
+      total,
+    };
+    base += job.files.len();
+    let result = tokio::task::spawn_blocking(move || {
+      if job.append {
+        bundler::append_to_bundle(&job.output_path, &job.files, &job.options, &progress)
+      } else {
+        bundler::build_bundle_with_progress(&job.files, &job.output_path, &job.options, &progress)
+      }
+    })
+    .await;
+
+    let summary = match result {
+      Ok(Ok(v)) => v,
+      Ok(Err(e)) if e.downcast_ref::<BundleCancelled>().is_some() => {
+        set_task_message(&state, &task_id, "cancelled", "任务已取消".to_string());
+        emit_event(&sink, &task_id, "warn", "cancelled", "任务已取消".to_string(), Some("cancelled".to_string()));
+        return;
+      }
+      Ok(Err(e)) => return fail(format!("整合失败: {:#}", e)),
+      Err(e) => return fail(format!("整合任务异常: {}", e)),
+    };
+
+    for conflict in &summary.conflicts {
+      let line = format!("[BUNDLE CONFLICT] {}", conflict.describe());
+      push_log(&state, &task_id, line.clone());
+      emit_event(&sink, &task_id, "warn", "bundle_conflict", line, Some("running".to_string()));
+    }
+    let line = format!(
+      "整合完成: {}（源文件 {}，写入 {}，已存在 {}，冲突 {}）",
+      summary.output_paths.join(", "),
+      summary.source_file_count,
+      summary.added.len(),
+      summary.already_present.len(),
+      summary.conflicts.len()
+    );
+    push_log(&state, &task_id, line.clone());
+    emit_event(&sink, &task_id, "info", "bundle_done", line, Some("running".to_string()));
+    // 全部源文件都被跳过时没有进度回调，在这里补齐。
+    update_task(&state, &task_id, |t| {
+      t.processed_ids = base;
+      t.bundle_output_paths.extend(summary.output_paths);
+    });
+  }
+
+  update_task(&state, &task_id, |t| {
+    t.status = "completed".to_string();
+    t.ended_at = Some(now_str());
+    t.message = Some("任务完成".to_string());
+  });
+  emit_event(&sink, &task_id, "info", "done", "整合任务完成".to_string(), Some("completed".to_string()));
+}
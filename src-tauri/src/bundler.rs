@@ -1,18 +1,187 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::io::{BufReader, Write};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use anyhow::{anyhow, Context, Result};
-use chrono::Local;
-use uuid::Uuid;
-use walkdir::WalkDir;
+use serde::{Deserialize, Serialize};
 use zip::write::FileOptions;
 use zip::{CompressionMethod, ZipArchive, ZipWriter};
 
+use crate::archive;
+use crate::bundle_index::{self, BundleIndex, BundleIndexChart, BundleIndexEntry, BUNDLE_INDEX_NAME};
+use crate::fsutil;
+
+/// 不同源文件写出相同条目路径时的处理方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+  /// 保留先出现的谱面，跳过后来的。
+  Skip,
+  /// 后来的谱面改放到 `<folder>_2` 等不冲突的文件夹。
+  #[default]
+  Rename,
+  /// 保留源文件修改时间较新的一方。
+  KeepNewest,
+}
+
+impl ConflictPolicy {
+  /// 任务参数中的写法：`skip` / `rename` / `keep_newest`。
+  pub fn parse(value: &str) -> Result<Self> {
+    match value.trim().to_ascii_lowercase().as_str() {
+      "skip" => Ok(Self::Skip),
+      "rename" => Ok(Self::Rename),
+      "keep_newest" | "keepnewest" | "newest" => Ok(Self::KeepNewest),
+      other => Err(anyhow!("unknown conflict policy: {}", other)),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BundleOptions {
+  pub conflict_policy: ConflictPolicy,
+  /// 每个分包的大致上限（字节）；单个谱面超过上限时独占一个分包。
+  pub max_bytes: Option<u64>,
+  /// 每个分包最多包含的谱面（源文件）数。
+  pub max_charts: Option<usize>,
+  /// 设置时在包内附带 `collections/<folder>/manifest.json`。
+  pub collection: Option<BundleCollection>,
+  /// 源文件 -> level ID，写入 `bundle_index.json`；未列出的源文件以文件名代替。
+  pub level_ids: HashMap<PathBuf, String>,
+}
+
+/// 随整合包导入的 AstroDX 清单；`levelIds` 只列出该包（分包）实际包含的谱面。
+#[derive(Debug, Clone, Default)]
+pub struct BundleCollection {
+  /// `collections/` 下的文件夹名。
+  pub folder: String,
+  pub name: String,
+  /// 源文件 -> level ID；不在其中的源文件不会写入清单。
+  pub level_ids: HashMap<PathBuf, String>,
+}
+
+impl BundleCollection {
+  pub fn entry_name(&self) -> String {
+    format!("collections/{}/manifest.json", self.folder)
+  }
+}
+
+impl BundleOptions {
+  /// 由任务参数构造；空字符串与 0 视为未设置。
+  pub fn from_params(conflict_policy: Option<&str>, max_bytes: Option<u64>, max_charts: Option<usize>) -> Result<Self> {
+    let conflict_policy = match conflict_policy {
+      Some(v) if !v.trim().is_empty() => ConflictPolicy::parse(v)?,
+      _ => ConflictPolicy::default(),
+    };
+    Ok(Self {
+      conflict_policy,
+      max_bytes: max_bytes.filter(|v| *v > 0),
+      max_charts: max_charts.filter(|v| *v > 0),
+      collection: None,
+      level_ids: HashMap::new(),
+    })
+  }
+
+  fn splits(&self) -> bool {
+    self.max_bytes.is_some() || self.max_charts.is_some()
+  }
+}
+
+/// 一次冲突：`incoming_source` 中有条目与已收录的 `existing_source` 同路径。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleConflict {
+  /// 第一条冲突的条目路径。
+  pub path: String,
+  pub entry_count: usize,
+  pub existing_source: String,
+  pub incoming_source: String,
+  /// skipped（跳过后来者）/ renamed（后来者改名）/ replaced（先前的被替换）。
+  pub action: String,
+  pub renamed_to: Option<String>,
+}
+
+impl BundleConflict {
+  pub fn describe(&self) -> String {
+    let detail = match (self.action.as_str(), &self.renamed_to) {
+      ("renamed", Some(folder)) => format!("后者改名为 {}", folder),
+      ("replaced", _) => "保留较新的后者".to_string(),
+      _ => "跳过后者".to_string(),
+    };
+    format!(
+      "{} 与 {} 有 {} 个同名条目（如 {}），{}",
+      self.existing_source, self.incoming_source, self.entry_count, self.path, detail
+    )
+  }
+}
+
+/// 整合过程的回调：每写完一个谱面报告一次，并在谱面之间等待暂停结束、检查是否取消。
+pub trait BundleProgress: Sync {
+  /// `done` / `total` 按源文件计；不写入的源文件（不存在、冲突时跳过或已在包内）在开始写入前即算作完成，
+  /// 因此最后一次报告时 `done == total`。
+  fn chart_done(&self, _done: usize, _total: usize, _source: &Path) {}
+
+  /// 暂停期间阻塞调用线程（整合运行在阻塞线程上）。
+  fn wait_while_paused(&self) {}
+
+  fn is_cancelled(&self) -> bool {
+    false
+  }
+}
+
+/// 不关心进度时使用。
+impl BundleProgress for () {}
+
+/// 整合被 `BundleProgress::is_cancelled` 中止；临时文件已清理。
+#[derive(Debug)]
+pub struct BundleCancelled;
+
+impl fmt::Display for BundleCancelled {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("bundle cancelled")
+  }
+}
+
+impl std::error::Error for BundleCancelled {}
+
 pub struct BundleSummary {
-  pub output_path: String,
+  /// 未分包时只有一项；分包时依次为 `_part01`、`_part02`……
+  pub output_paths: Vec<String>,
   pub source_file_count: usize,
   pub processed_count: usize,
+  pub conflicts: Vec<BundleConflict>,
+  /// 写入的谱面（源文件名）。
+  pub added: Vec<String>,
+  /// 追加时已在整合包中、因此未写入的谱面（源文件名）。
+  pub already_present: Vec<String>,
+}
+
+/// 每个条目在本地头与中央目录中的固定开销（不含文件名）。
+const ENTRY_OVERHEAD: u64 = 30 + 46;
+
+/// 输出包中的一个条目：来自第 `source` 个源文件的第 `index` 个条目。
+struct PlannedEntry {
+  source: usize,
+  index: usize,
+  name: String,
+  /// 压缩后大小加上本地头与中央目录的开销，用于分包估算。
+  size: u64,
+}
+
+/// 一个源文件在输出包中的位置：所有条目都放在 `root/` 下。
+struct SourcePlan {
+  root: String,
+  /// (条目序号, 相对 root 的路径, 压缩后大小)
+  entries: Vec<(usize, String, u64)>,
+  modified: Option<SystemTime>,
+  included: bool,
+}
+
+impl SourcePlan {
+  fn names(&self) -> impl Iterator<Item = String> + '_ {
+    self.entries.iter().map(|(_, rel, _)| format!("{}/{}", self.root, rel))
+  }
 }
 
 fn ensure_parent(path: &Path) -> Result<()> {
@@ -22,109 +191,453 @@ fn ensure_parent(path: &Path) -> Result<()> {
   Ok(())
 }
 
-pub fn build_bundle_from_files(
-  source_files: &[PathBuf],
-  output_path: &Path,
-) -> Result<BundleSummary> {
-  if source_files.is_empty() {
-    return Err(anyhow!("no source files for bundling"));
-  }
-
-  ensure_parent(output_path)?;
+fn open_archive(path: &Path) -> Result<ZipArchive<BufReader<File>>> {
+  let file = File::open(path).with_context(|| format!("open source failed: {}", path.display()))?;
+  ZipArchive::new(BufReader::new(file)).with_context(|| format!("read zip archive failed: {}", path.display()))
+}
 
-  let ts = Local::now().format("%Y%m%d_%H%M%S").to_string();
-  let work_root = std::env::temp_dir().join(format!("niconico_bundle_{}_{}", ts, Uuid::new_v4()));
-  let zip_copies = work_root.join("zip_copies");
-  let extracted = work_root.join("extracted");
-  fs::create_dir_all(&zip_copies)?;
-  fs::create_dir_all(&extracted)?;
+fn read_source_plan(src: &Path) -> Result<SourcePlan> {
+  let stem = src
+    .file_stem()
+    .and_then(|s| s.to_str())
+    .ok_or_else(|| anyhow!("invalid source stem: {}", src.display()))?;
+  let mut archive = open_archive(src)?;
 
-  let mut processed_count = 0usize;
-  for src in source_files {
-    if !src.exists() {
+  let mut seen = HashSet::new();
+  let mut entries = Vec::new();
+  for index in 0..archive.len() {
+    let entry = archive.by_index_raw(index)?;
+    if entry.is_dir() {
+      continue;
+    }
+    let Some(enclosed) = entry.enclosed_name() else {
       continue;
+    };
+    let rel = enclosed.to_string_lossy().replace('\\', "/");
+    if seen.insert(rel.clone()) {
+      entries.push((index, rel, entry.compressed_size()));
     }
+  }
 
-    let stem = src
-      .file_stem()
-      .and_then(|s| s.to_str())
-      .ok_or_else(|| anyhow!("invalid source stem: {}", src.display()))?;
-
-    let zip_copy = zip_copies.join(format!("{}.zip", stem));
-    fs::copy(src, &zip_copy).with_context(|| {
-      format!(
-        "copy source to zip failed: {} -> {}",
-        src.display(),
-        zip_copy.display()
-      )
-    })?;
-
-    let extract_dir = extracted.join(stem);
-    fs::create_dir_all(&extract_dir)?;
-
-    let mut archive = ZipArchive::new(
-      File::open(&zip_copy)
-        .with_context(|| format!("open zip copy failed: {}", zip_copy.display()))?,
-    )
-    .with_context(|| format!("read zip archive failed: {}", zip_copy.display()))?;
+  Ok(SourcePlan {
+    root: stem.to_string(),
+    entries,
+    modified: fs::metadata(src).and_then(|m| m.modified()).ok(),
+    included: true,
+  })
+}
+
+fn display_name(path: &Path) -> String {
+  path
+    .file_name()
+    .map(|s| s.to_string_lossy().to_string())
+    .unwrap_or_else(|| path.display().to_string())
+}
+
+/// 输出包里已经存在、不能被覆盖的条目（追加到已有整合包时）。
+#[derive(Default)]
+struct Reserved {
+  label: String,
+  names: HashSet<String>,
+  roots: HashSet<String>,
+}
 
-    for i in 0..archive.len() {
-      let mut entry = archive.by_index(i)?;
-      let enclosed = match entry.enclosed_name() {
-        Some(path) => path.to_path_buf(),
-        None => continue,
+/// 只读各源文件的中央目录，决定输出包里有哪些条目，并按 `policy` 处理跨源文件的同名条目。
+fn plan_entries(
+  sources: &[&PathBuf],
+  policy: ConflictPolicy,
+  reserved: &Reserved,
+) -> Result<(Vec<PlannedEntry>, Vec<BundleConflict>)> {
+  let mut plans: Vec<SourcePlan> = Vec::with_capacity(sources.len());
+  // 已收录的条目路径 -> 所属源文件
+  let mut owners: HashMap<String, usize> = HashMap::new();
+  let mut conflicts = Vec::new();
+
+  for (source, src) in sources.iter().enumerate() {
+    let mut plan = read_source_plan(src)?;
+
+    let mut clashes: BTreeMap<usize, (String, usize)> = BTreeMap::new();
+    let mut reserved_hit: Option<(String, usize)> = None;
+    for name in plan.names() {
+      if reserved.names.contains(&name) {
+        reserved_hit.get_or_insert_with(|| (name.clone(), 0)).1 += 1;
+      }
+      if let Some(&owner) = owners.get(&name) {
+        clashes.entry(owner).or_insert_with(|| (name, 0)).1 += 1;
+      }
+    }
+
+    if !clashes.is_empty() || reserved_hit.is_some() {
+      // 已有整合包中的条目不会被替换。
+      let newest = reserved_hit.is_none()
+        && clashes
+          .keys()
+          .all(|&owner| matches!((plan.modified, plans[owner].modified), (Some(a), Some(b)) if a > b));
+      let renamed_to = match policy {
+        ConflictPolicy::Rename => {
+          let mut taken: HashSet<&str> = plans.iter().map(|p| p.root.as_str()).collect();
+          taken.extend(reserved.roots.iter().map(String::as_str));
+          let root = (2..)
+            .map(|n| format!("{}_{}", plan.root, n))
+            .find(|r| !taken.contains(r.as_str()))
+            .unwrap_or_default();
+          Some(root)
+        }
+        _ => None,
+      };
+      let action = match policy {
+        ConflictPolicy::Skip => "skipped",
+        ConflictPolicy::Rename => "renamed",
+        ConflictPolicy::KeepNewest if newest => "replaced",
+        ConflictPolicy::KeepNewest => "skipped",
       };
 
-      let out_path = extract_dir.join(enclosed);
-      if entry.is_dir() {
-        fs::create_dir_all(&out_path)?;
-      } else {
-        if let Some(parent) = out_path.parent() {
-          fs::create_dir_all(parent)?;
+      let existing = reserved_hit
+        .iter()
+        .map(|hit| (reserved.label.clone(), hit))
+        .chain(clashes.iter().map(|(&owner, hit)| (display_name(sources[owner]), hit)));
+      for (existing_source, (path, entry_count)) in existing {
+        conflicts.push(BundleConflict {
+          path: path.clone(),
+          entry_count: *entry_count,
+          existing_source,
+          incoming_source: display_name(src),
+          action: action.to_string(),
+          renamed_to: renamed_to.clone(),
+        });
+      }
+
+      match action {
+        "renamed" => plan.root = renamed_to.unwrap_or_default(),
+        "replaced" => {
+          for &owner in clashes.keys() {
+            plans[owner].included = false;
+            owners.retain(|_, o| *o != owner);
+          }
         }
-        let mut out_file = File::create(&out_path)?;
-        std::io::copy(&mut entry, &mut out_file)?;
+        _ => plan.included = false,
       }
     }
 
-    processed_count += 1;
+    if plan.included {
+      for name in plan.names() {
+        owners.insert(name, source);
+      }
+    }
+    plans.push(plan);
+  }
+
+  let planned = plans
+    .into_iter()
+    .enumerate()
+    .filter(|(_, plan)| plan.included)
+    .flat_map(|(source, plan)| {
+      let root = plan.root;
+      plan.entries.into_iter().map(move |(index, rel, compressed)| {
+        let name = format!("{}/{}", root, rel);
+        PlannedEntry {
+          source,
+          index,
+          size: compressed + ENTRY_OVERHEAD + 2 * name.len() as u64,
+          name,
+        }
+      })
+    })
+    .collect();
+  Ok((planned, conflicts))
+}
+
+/// 以谱面（源文件）为单位切分，同一谱面的条目不会跨分包。
+fn split_parts<'a>(planned: &'a [PlannedEntry], options: &BundleOptions) -> Vec<Vec<&'a PlannedEntry>> {
+  let mut parts: Vec<Vec<&PlannedEntry>> = Vec::new();
+  let mut current: Vec<&PlannedEntry> = Vec::new();
+  let mut bytes = 0u64;
+  let mut charts = 0usize;
+
+  for chart in planned.chunk_by(|a, b| a.source == b.source) {
+    let chart_bytes: u64 = chart.iter().map(|p| p.size).sum();
+    let over_bytes = options.max_bytes.is_some_and(|max| bytes + chart_bytes > max);
+    let over_charts = options.max_charts.is_some_and(|max| charts >= max.max(1));
+    if charts > 0 && (over_bytes || over_charts) {
+      parts.push(std::mem::take(&mut current));
+      bytes = 0;
+      charts = 0;
+    }
+    current.extend(chart);
+    bytes += chart_bytes;
+    charts += 1;
+  }
+  if !current.is_empty() || parts.is_empty() {
+    parts.push(current);
   }
+  parts
+}
+
+/// `bundle.adx` 的第 n 个分包：`bundle_part01.adx`。
+pub fn part_path(output_path: &Path, n: usize) -> PathBuf {
+  let stem = output_path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+  let name = match output_path.extension() {
+    Some(ext) => format!("{}_part{:02}.{}", stem, n, ext.to_string_lossy()),
+    None => format!("{}_part{:02}", stem, n),
+  };
+  output_path.with_file_name(name)
+}
 
-  let zip_file = File::create(output_path)
-    .with_context(|| format!("create output failed: {}", output_path.display()))?;
+/// 把压缩数据从各源文件原样拷入输出包，不解压、不落临时目录；内存占用与包大小无关。
+/// 每个条目另外解压一遍计算 SHA-256，最后在包根写入 `bundle_index.json`。
+fn write_entries(
+  sources: &[&PathBuf],
+  planned: &[&PlannedEntry],
+  output_path: &Path,
+  existing: Option<&Path>,
+  options: &BundleOptions,
+  progress: &mut ProgressCounter,
+) -> Result<()> {
+  let collection = options.collection.as_ref();
+  let zip_file = File::create(output_path).with_context(|| format!("create output failed: {}", output_path.display()))?;
   let mut writer = ZipWriter::new(zip_file);
-  let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+  let mut level_ids = Vec::new();
+  let mut charts = Vec::new();
 
-  for entry in WalkDir::new(&extracted).into_iter().flatten() {
-    if !entry.file_type().is_file() {
-      continue;
+  if let Some(existing) = existing {
+    let mut archive = open_archive(existing)?;
+    for index in 0..archive.len() {
+      let entry = archive.by_index_raw(index)?;
+      if entry.name() == BUNDLE_INDEX_NAME {
+        continue;
+      }
+      writer
+        .raw_copy_file(entry)
+        .with_context(|| format!("copy existing entry failed: {}", existing.display()))?;
     }
+    charts = match bundle_index::read_index(&mut archive)? {
+      Some(index) => index.charts,
+      None => bundle_index::index_by_root(&mut archive, &display_name(existing))?,
+    };
+  }
 
-    let path = entry.path();
-    let rel = path
-      .strip_prefix(&extracted)
-      .map_err(|e| anyhow!("strip prefix failed: {}", e))?;
-    let rel_str = rel
-      .to_string_lossy()
-      .replace('\\', "/");
+  for group in planned.chunk_by(|a, b| a.source == b.source) {
+    progress.hook.wait_while_paused();
+    if progress.hook.is_cancelled() {
+      return Err(BundleCancelled.into());
+    }
+    let src = sources[group[0].source];
+    let mut archive = open_archive(src)?;
+    let mut entries = Vec::with_capacity(group.len());
+    for item in group {
+      let (size, sha256) = {
+        let mut entry = archive
+          .by_index(item.index)
+          .with_context(|| format!("read entry failed: {} in {}", item.name, src.display()))?;
+        bundle_index::hash_entry(&mut entry)
+          .with_context(|| format!("hash entry failed: {} in {}", item.name, src.display()))?
+      };
+      entries.push(BundleIndexEntry {
+        name: item.name.clone(),
+        size,
+        sha256,
+      });
+      let entry = archive
+        .by_index_raw(item.index)
+        .with_context(|| format!("read entry failed: {} in {}", item.name, src.display()))?;
+      writer
+        .raw_copy_file_rename(entry, item.name.as_str())
+        .with_context(|| format!("copy entry failed: {}", item.name))?;
+    }
+    let collection_id = collection.and_then(|c| c.level_ids.get(src.as_path()));
+    if let Some(id) = collection_id {
+      level_ids.push(id.clone());
+    }
+    charts.push(BundleIndexChart {
+      level_id: collection_id
+        .or_else(|| options.level_ids.get(src.as_path()))
+        .cloned()
+        .or_else(|| src.file_stem().map(|s| s.to_string_lossy().to_string()))
+        .unwrap_or_default(),
+      source_file: display_name(src),
+      folders: sorted(chart_folders(entries.iter().map(|e| e.name.as_str()))),
+      entries,
+    });
+    progress.done += 1;
+    progress.hook.chart_done(progress.done, progress.total, src);
+  }
 
+  let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+  if let Some(collection) = collection {
+    let manifest = serde_json::json!({ "name": collection.name, "levelIds": level_ids });
     writer
-      .start_file(rel_str, options)
+      .start_file(collection.entry_name(), options)
       .context("zip start_file failed")?;
-
-    let mut input = File::open(path)?;
-    let mut buf = Vec::new();
-    input.read_to_end(&mut buf)?;
-    writer.write_all(&buf)?;
+    writer.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
   }
 
+  writer
+    .start_file(BUNDLE_INDEX_NAME, options)
+    .context("zip start_file failed")?;
+  writer.write_all(&serde_json::to_vec_pretty(&BundleIndex::new(charts))?)?;
+
   writer.finish().context("zip finish failed")?;
+  Ok(())
+}
+
+fn sorted(set: HashSet<String>) -> Vec<String> {
+  let mut items: Vec<String> = set.into_iter().collect();
+  items.sort();
+  items
+}
+
+struct ProgressCounter<'a> {
+  hook: &'a dyn BundleProgress,
+  done: usize,
+  total: usize,
+}
+
+pub fn build_bundle_from_files(
+  source_files: &[PathBuf],
+  output_path: &Path,
+  options: &BundleOptions,
+) -> Result<BundleSummary> {
+  build_bundle_with_progress(source_files, output_path, options, &())
+}
+
+pub fn build_bundle_with_progress(
+  source_files: &[PathBuf],
+  output_path: &Path,
+  options: &BundleOptions,
+  progress: &dyn BundleProgress,
+) -> Result<BundleSummary> {
+  if source_files.is_empty() {
+    return Err(anyhow!("no source files for bundling"));
+  }
+
+  ensure_parent(output_path)?;
+
+  let sources: Vec<&PathBuf> = source_files.iter().filter(|p| p.exists()).collect();
+  let (planned, conflicts) = plan_entries(&sources, options.conflict_policy, &Reserved::default())?;
+
+  let parts = split_parts(&planned, options);
+  let written_charts = planned.chunk_by(|a, b| a.source == b.source).count();
+  let mut counter = ProgressCounter {
+    hook: progress,
+    done: source_files.len() - written_charts,
+    total: source_files.len(),
+  };
+  let targets: Vec<PathBuf> = if options.splits() {
+    (1..=parts.len()).map(|n| part_path(output_path, n)).collect()
+  } else {
+    vec![output_path.to_path_buf()]
+  };
+
+  // 全部分包写完后才逐个 rename，中途失败不会留下半套分包。
+  let tmps: Vec<PathBuf> = targets.iter().map(|t| fsutil::tmp_path_for(t)).collect();
+  let written = parts
+    .iter()
+    .zip(&tmps)
+    .try_for_each(|(part, tmp)| write_entries(&sources, part, tmp, None, options, &mut counter))
+    .and_then(|_| {
+      tmps.iter().zip(&targets).try_for_each(|(tmp, target)| {
+        fs::rename(tmp, target).with_context(|| format!("rename output failed: {}", target.display()))
+      })
+    });
+  if let Err(e) = written {
+    for tmp in &tmps {
+      let _ = fs::remove_file(tmp);
+    }
+    return Err(e);
+  }
+
+  Ok(BundleSummary {
+    output_paths: targets.iter().map(|t| t.to_string_lossy().to_string()).collect(),
+    source_file_count: source_files.len(),
+    processed_count: sources.len(),
+    conflicts,
+    added: added_sources(&sources, &planned),
+    already_present: Vec::new(),
+  })
+}
+
+fn added_sources(sources: &[&PathBuf], planned: &[PlannedEntry]) -> Vec<String> {
+  planned
+    .chunk_by(|a, b| a.source == b.source)
+    .map(|group| display_name(sources[group[0].source]))
+    .collect()
+}
+
+/// 整合包中已有的谱面文件夹（`maidata.txt` 所在目录）。
+fn chart_folders<S: AsRef<str>>(names: impl IntoIterator<Item = S>) -> HashSet<String> {
+  names
+    .into_iter()
+    .filter_map(|n| archive::chart_folder_of(n.as_ref()).map(str::to_string))
+    .collect()
+}
+
+/// 把 `source_files` 中尚未收录的谱面追加到已有整合包 `bundle_path`。
+/// 源文件的所有谱面文件夹都已在包内时视为已存在；`bundle_path` 自身即使在列表中也会被忽略。
+/// 结果先写入临时文件再替换原包。
+pub fn append_to_bundle(
+  bundle_path: &Path,
+  source_files: &[PathBuf],
+  options: &BundleOptions,
+  progress: &dyn BundleProgress,
+) -> Result<BundleSummary> {
+  if options.splits() {
+    return Err(anyhow!("splitting is not supported when appending to a bundle"));
+  }
+  let existing = open_archive(bundle_path)?;
+  let names: HashSet<String> = existing.file_names().map(str::to_string).collect();
+  drop(existing);
+  let existing_folders = chart_folders(&names);
 
-  let _ = fs::remove_dir_all(&work_root);
+  let mut already_present = Vec::new();
+  let mut sources: Vec<&PathBuf> = Vec::new();
+  let candidates = source_files
+    .iter()
+    .filter(|p| p.exists() && !fsutil::same_file(p, bundle_path));
+  for src in candidates {
+    let plan = read_source_plan(src)?;
+    let folders = chart_folders(plan.names());
+    if !folders.is_empty() && folders.is_subset(&existing_folders) {
+      already_present.push(display_name(src));
+    } else {
+      sources.push(src);
+    }
+  }
+
+  let reserved = Reserved {
+    label: display_name(bundle_path),
+    roots: names.iter().filter_map(|n| n.split_once('/')).map(|(root, _)| root.to_string()).collect(),
+    names,
+  };
+  let (planned, conflicts) = plan_entries(&sources, options.conflict_policy, &reserved)?;
+  let added = added_sources(&sources, &planned);
+
+  if !planned.is_empty() {
+    let part: Vec<&PlannedEntry> = planned.iter().collect();
+    let mut counter = ProgressCounter {
+      hook: progress,
+      done: source_files.len() - added.len(),
+      total: source_files.len(),
+    };
+    let tmp = fsutil::tmp_path_for(bundle_path);
+    let append_options = BundleOptions {
+      collection: None,
+      ..options.clone()
+    };
+    let written = write_entries(&sources, &part, &tmp, Some(bundle_path), &append_options, &mut counter).and_then(|_| {
+      fs::rename(&tmp, bundle_path).with_context(|| format!("rename output failed: {}", bundle_path.display()))
+    });
+    if let Err(e) = written {
+      let _ = fs::remove_file(&tmp);
+      return Err(e);
+    }
+  }
 
   Ok(BundleSummary {
-    output_path: output_path.to_string_lossy().to_string(),
+    output_paths: vec![bundle_path.to_string_lossy().to_string()],
     source_file_count: source_files.len(),
-    processed_count,
+    processed_count: sources.len() + already_present.len(),
+    conflicts,
+    added,
+    already_present,
   })
 }
@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use zip::ZipArchive;
+
+use crate::archive;
+use crate::fsutil;
+use crate::library;
+use crate::maidata::{self, ChartMeta};
+use crate::models::FailItem;
+
+/// 索引文件与谱面放在同一个输出目录中。
+pub const INDEX_FILE_NAME: &str = "chart_index.json";
+const MAX_MAIDATA_BYTES: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChartIndexEntry {
+  pub level_id: String,
+  pub file_name: String,
+  /// 压缩包内 maidata.txt 所在的文件夹。
+  pub folder: String,
+  pub size: u64,
+  pub modified: Option<u64>,
+  pub meta: ChartMeta,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChartIndex {
+  pub entries: BTreeMap<String, ChartIndexEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChartIndexSummary {
+  pub index_path: String,
+  pub total: usize,
+  pub parsed: usize,
+  pub reused: usize,
+  pub fail_items: Vec<FailItem>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChartQuery {
+  pub title: Option<String>,
+  pub artist: Option<String>,
+  pub charter: Option<String>,
+  /// 限定难度（如 `master`、`remaster`）；为空时任一难度满足即可。
+  pub difficulty: Option<String>,
+  pub level_min: Option<f64>,
+  pub level_max: Option<f64>,
+}
+
+pub fn index_path(output_dir: &Path) -> PathBuf {
+  output_dir.join(INDEX_FILE_NAME)
+}
+
+pub fn load_index(output_dir: &Path) -> ChartIndex {
+  fs::read(index_path(output_dir))
+    .ok()
+    .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+    .unwrap_or_default()
+}
+
+pub fn save_index(output_dir: &Path, index: &ChartIndex) -> Result<()> {
+  let bytes = serde_json::to_vec_pretty(index)?;
+  fsutil::write_atomic(&index_path(output_dir), &bytes)
+}
+
+/// 读取谱面包中第一个谱面文件夹的 maidata.txt。
+pub fn read_chart_meta(path: &Path) -> Result<(String, ChartMeta)> {
+  let file = File::open(path).with_context(|| format!("open archive failed: {}", path.display()))?;
+  let mut zip = ZipArchive::new(file).with_context(|| format!("read zip archive failed: {}", path.display()))?;
+
+  let entry_name = zip
+    .file_names()
+    .filter(|n| archive::chart_folder_of(n).is_some())
+    .min()
+    .map(|s| s.to_string())
+    .ok_or_else(|| anyhow!("no maidata.txt in {}", path.display()))?;
+  let folder = archive::chart_folder_of(&entry_name).unwrap_or_default().to_string();
+  let has_bga = archive::folder_has_video(zip.file_names(), &folder);
+
+  let entry = zip.by_name(&entry_name)?;
+  let mut bytes = Vec::new();
+  entry.take(MAX_MAIDATA_BYTES).read_to_end(&mut bytes)?;
+  let mut meta = maidata::parse_maidata(&String::from_utf8_lossy(&bytes));
+  meta.has_bga = Some(has_bga);
+  Ok((folder, meta))
+}
+
+fn modified_secs(meta: &fs::Metadata) -> Option<u64> {
+  meta
+    .modified()
+    .ok()
+    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+    .map(|d| d.as_secs())
+}
+
+/// 扫描输出目录并更新索引；大小和修改时间未变的文件沿用旧结果。
+pub fn build_index(output_dir: &Path, manifest_paths: &[String]) -> Result<ChartIndexSummary> {
+  let report = library::scan_library(output_dir, manifest_paths)?;
+  let previous = load_index(output_dir);
+  let mut next = ChartIndex::default();
+  let mut parsed = 0usize;
+  let mut reused = 0usize;
+  let mut fail_items = Vec::new();
+
+  for file in report.files.iter().filter(|f| f.status == "ok") {
+    let Some(level_id) = file.level_id.clone() else {
+      continue;
+    };
+    let path = PathBuf::from(&file.path);
+    let modified = fs::metadata(&path).ok().as_ref().and_then(modified_secs);
+
+    if let Some(old) = previous.entries.get(&level_id) {
+      if old.file_name == file.file_name && old.size == file.size && old.modified == modified {
+        next.entries.insert(level_id, old.clone());
+        reused += 1;
+        continue;
+      }
+    }
+
+    match read_chart_meta(&path) {
+      Ok((folder, meta)) => {
+        next.entries.insert(
+          level_id.clone(),
+          ChartIndexEntry {
+            level_id,
+            file_name: file.file_name.clone(),
+            folder,
+            size: file.size,
+            modified,
+            meta,
+          },
+        );
+        parsed += 1;
+      }
+      Err(e) => fail_items.push(FailItem {
+        id: level_id,
+        reason: format!("{:#}", e),
+      }),
+    }
+  }
+
+  save_index(output_dir, &next)?;
+  Ok(ChartIndexSummary {
+    index_path: index_path(output_dir).to_string_lossy().to_string(),
+    total: next.entries.len(),
+    parsed,
+    reused,
+    fail_items,
+  })
+}
+
+fn contains_ci(haystack: Option<&str>, needle: &str) -> bool {
+  haystack.is_some_and(|h| h.to_lowercase().contains(&needle.to_lowercase()))
+}
+
+pub fn matches_query(meta: &ChartMeta, query: &ChartQuery) -> bool {
+  if let Some(title) = query.title.as_deref().filter(|s| !s.trim().is_empty()) {
+    if !contains_ci(meta.title.as_deref(), title.trim()) {
+      return false;
+    }
+  }
+  if let Some(artist) = query.artist.as_deref().filter(|s| !s.trim().is_empty()) {
+    if !contains_ci(meta.artist.as_deref(), artist.trim()) {
+      return false;
+    }
+  }
+  if let Some(charter) = query.charter.as_deref().filter(|s| !s.trim().is_empty()) {
+    if !meta.designers().iter().any(|d| contains_ci(Some(d), charter.trim())) {
+      return false;
+    }
+  }
+
+  let wants_level = query.level_min.is_some() || query.level_max.is_some();
+  let difficulty = query.difficulty.as_deref().filter(|s| !s.trim().is_empty());
+  if !wants_level && difficulty.is_none() {
+    return true;
+  }
+
+  meta
+    .difficulties
+    .iter()
+    .filter(|d| d.has_chart)
+    .filter(|d| difficulty.is_none_or(|name| d.name.eq_ignore_ascii_case(name.trim())))
+    .any(|d| {
+      if !wants_level {
+        return true;
+      }
+      let Some(value) = d.level.as_deref().and_then(maidata::level_value) else {
+        return false;
+      };
+      query.level_min.is_none_or(|min| value >= min) && query.level_max.is_none_or(|max| value <= max)
+    })
+}
+
+pub fn query_index(index: &ChartIndex, query: &ChartQuery) -> Vec<ChartIndexEntry> {
+  let mut out: Vec<ChartIndexEntry> = index
+    .entries
+    .values()
+    .filter(|e| matches_query(&e.meta, query))
+    .cloned()
+    .collect();
+  out.sort_by(|a, b| a.meta.title.cmp(&b.meta.title).then_with(|| a.level_id.cmp(&b.level_id)));
+  out
+}
@@ -1,13 +1,18 @@
-use std::collections::HashMap;
-use std::fs::File;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
 use std::path::{Path, PathBuf};
 
 use serde::Deserialize;
 use serde_json::Value;
-use tauri::{AppHandle, Manager};
 use walkdir::WalkDir;
 
-use crate::models::{CollectionManifestMeta, ParsedManifest};
+use crate::downloader::sanitize_id_for_filename;
+use crate::fsutil;
+use crate::library;
+use crate::models::{
+  CollectionDiff, CollectionExportReport, CollectionListing, CollectionManifestMeta, ExportedManifest, FailItem,
+  ManifestDiff, ManifestError, ParsedManifest,
+};
 
 #[derive(Debug, Deserialize)]
 struct RawManifest {
@@ -35,13 +40,35 @@ fn normalize_level_id(v: &Value) -> Option<String> {
 }
 
 pub fn parse_manifest_file(path: &Path) -> Result<ParsedManifest, String> {
-  let file = File::open(path).map_err(|e| format!("open manifest failed {}: {}", path.display(), e))?;
-  let raw: RawManifest =
-    serde_json::from_reader(file).map_err(|e| format!("parse manifest failed {}: {}", path.display(), e))?;
+  check_manifest_file(path).map_err(|e| e.message)
+}
 
-  let level_ids_raw = raw
-    .level_ids
-    .ok_or_else(|| format!("manifest missing levelIds: {}", path.display()))?;
+fn manifest_error(path: &Path, kind: &str, message: String) -> ManifestError {
+  ManifestError {
+    path: path.to_string_lossy().to_string(),
+    kind: kind.to_string(),
+    message,
+    line: None,
+    column: None,
+  }
+}
+
+/// 与 `parse_manifest_file` 相同，但失败时给出错误类别以及 JSON 错误的行列。
+pub fn check_manifest_file(path: &Path) -> Result<ParsedManifest, ManifestError> {
+  let bytes =
+    fs::read(path).map_err(|e| manifest_error(path, "io", format!("open manifest failed {}: {}", path.display(), e)))?;
+  if bytes.iter().all(u8::is_ascii_whitespace) {
+    return Err(manifest_error(path, "empty", format!("manifest is empty: {}", path.display())));
+  }
+  let raw: RawManifest = serde_json::from_slice(&bytes).map_err(|e| ManifestError {
+    line: Some(e.line()),
+    column: Some(e.column()),
+    ..manifest_error(path, "json", format!("parse manifest failed {}: {}", path.display(), e))
+  })?;
+
+  let level_ids_raw = raw.level_ids.ok_or_else(|| {
+    manifest_error(path, "missing_level_ids", format!("manifest missing levelIds: {}", path.display()))
+  })?;
 
   let mut level_ids = Vec::with_capacity(level_ids_raw.len());
   for v in &level_ids_raw {
@@ -51,7 +78,7 @@ pub fn parse_manifest_file(path: &Path) -> Result<ParsedManifest, String> {
   }
 
   if level_ids.is_empty() {
-    return Err(format!("manifest has empty levelIds: {}", path.display()));
+    return Err(manifest_error(path, "empty", format!("manifest has empty levelIds: {}", path.display())));
   }
 
   let default_name = path
@@ -67,7 +94,7 @@ pub fn parse_manifest_file(path: &Path) -> Result<ParsedManifest, String> {
   })
 }
 
-fn collect_manifest_files(root: &Path) -> Vec<PathBuf> {
+pub fn collect_manifest_files(root: &Path) -> Vec<PathBuf> {
   let mut files = Vec::new();
   for e in WalkDir::new(root).into_iter().flatten() {
     if e.file_type().is_file() && e.file_name() == "manifest.json" {
@@ -82,7 +109,7 @@ fn has_manifest_json(root: &Path) -> bool {
   root.exists() && !collect_manifest_files(root).is_empty()
 }
 
-fn builtin_dir_candidates(app: &AppHandle) -> Vec<PathBuf> {
+fn builtin_dir_candidates(resource_dir: Option<&Path>) -> Vec<PathBuf> {
   let mut out = Vec::new();
 
   if let Ok(dir) = std::env::var("NICONICO_COLLECTIONS_DIR") {
@@ -102,85 +129,455 @@ fn builtin_dir_candidates(app: &AppHandle) -> Vec<PathBuf> {
   // src-tauri Cargo.toml 所在目录，开发态稳定可用。
   out.push(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../collections"));
 
-  if let Ok(resource) = app.path().resource_dir() {
+  if let Some(resource) = resource_dir {
     out.push(resource.join("collections"));
     // 某些打包配置会直接把资源内容放进 resource 根目录。
-    out.push(resource);
+    out.push(resource.to_path_buf());
   }
 
   out
 }
 
-fn resolve_builtin_collections_dir(app: &AppHandle) -> Option<PathBuf> {
-  for candidate in builtin_dir_candidates(app) {
-    if has_manifest_json(&candidate) {
-      return Some(candidate);
-    }
+fn resolve_builtin_collections_dir(resource_dir: Option<&Path>) -> Option<PathBuf> {
+  builtin_dir_candidates(resource_dir)
+    .into_iter()
+    .find(|candidate| has_manifest_json(candidate))
+}
+
+fn relative_path(root: &Path, path: &Path) -> String {
+  path
+    .strip_prefix(root)
+    .ok()
+    .and_then(|p| p.to_str().map(|s| s.replace('\\', "/")))
+    .unwrap_or_else(|| path.to_string_lossy().to_string())
+}
+
+pub(crate) fn manifest_meta(root: &Path, path: &Path, source: &str, parsed: ParsedManifest) -> CollectionManifestMeta {
+  let rel = relative_path(root, path);
+  CollectionManifestMeta {
+    id: format!("{}:{}", source, rel),
+    name: parsed.name,
+    path: path.to_string_lossy().to_string(),
+    relative_path: rel,
+    level_count: parsed.level_ids.len(),
+    source: source.to_string(),
   }
-  None
 }
 
-fn collect_from_source(root: &Path, source: &str) -> Result<Vec<CollectionManifestMeta>, String> {
-  let mut out = Vec::new();
+/// 解析 `root` 下全部清单；无法解析的逐个记入 `errors`，不影响其他清单。
+pub fn collect_from_source(root: &Path, source: &str) -> CollectionListing {
+  let mut out = CollectionListing::default();
   for path in collect_manifest_files(root) {
-    let parsed = parse_manifest_file(&path)?;
-    let rel = path
-      .strip_prefix(root)
-      .ok()
-      .and_then(|p| p.to_str().map(|s| s.replace('\\', "/")))
-      .unwrap_or_else(|| path.to_string_lossy().to_string());
-
-    out.push(CollectionManifestMeta {
-      id: format!("{}:{}", source, rel),
-      name: parsed.name,
-      path: path.to_string_lossy().to_string(),
-      relative_path: rel,
-      level_count: parsed.level_ids.len(),
-      source: source.to_string(),
-    });
+    match check_manifest_file(&path) {
+      Ok(parsed) => out.manifests.push(manifest_meta(root, &path, source, parsed)),
+      Err(e) => out.errors.push(e),
+    }
   }
-  Ok(out)
+  out
 }
 
-pub fn list_collections(
-  app: &AppHandle,
-  overlay: Option<PathBuf>,
-) -> Result<Vec<CollectionManifestMeta>, String> {
-  let builtin_root = resolve_builtin_collections_dir(app).ok_or_else(|| {
-    let tried = builtin_dir_candidates(app)
+/// `resource_dir` 为应用资源目录（桌面端）；命令行下为 None。
+pub fn builtin_collections_dir(resource_dir: Option<&Path>) -> Result<PathBuf, String> {
+  resolve_builtin_collections_dir(resource_dir).ok_or_else(|| {
+    let tried = builtin_dir_candidates(resource_dir)
       .into_iter()
       .map(|p| p.to_string_lossy().to_string())
       .collect::<Vec<_>>()
       .join(" | ");
     format!("cannot find builtin collections directory; tried: {}", tried)
-  })?;
+  })
+}
+
+pub fn list_collections(resource_dir: Option<&Path>, overlay: Option<PathBuf>) -> Result<CollectionListing, String> {
+  let builtin_root = builtin_collections_dir(resource_dir)?;
+  let overlay_root = overlay.filter(|root| root.exists());
+  Ok(merge_collections(&builtin_root, overlay_root.as_deref()))
+}
 
+/// 合并内置与外部目录的清单，外部目录中相同相对路径的清单覆盖内置版本。
+pub fn merge_collections(builtin_root: &Path, overlay_root: Option<&Path>) -> CollectionListing {
   let mut merged: HashMap<String, CollectionManifestMeta> = HashMap::new();
+  let mut errors = Vec::new();
 
-  for item in collect_from_source(&builtin_root, "builtin")? {
-    merged.insert(item.relative_path.clone(), item);
+  let mut sources = vec![collect_from_source(builtin_root, "builtin")];
+  if let Some(root) = overlay_root {
+    sources.push(collect_from_source(root, "overlay"));
   }
-
-  if let Some(overlay_root) = overlay {
-    if overlay_root.exists() {
-      for item in collect_from_source(&overlay_root, "overlay")? {
-        merged.insert(item.relative_path.clone(), item);
-      }
+  for listing in sources {
+    for item in listing.manifests {
+      merged.insert(item.relative_path.clone(), item);
     }
+    errors.extend(listing.errors);
   }
 
-  let mut values: Vec<_> = merged.into_values().collect();
-  values.sort_by(|a, b| a.name.cmp(&b.name));
-  Ok(values)
+  let mut manifests: Vec<_> = merged.into_values().collect();
+  manifests.sort_by(|a, b| a.name.cmp(&b.name));
+  CollectionListing { manifests, errors }
 }
 
-pub fn validate_collections_dir(dir: &Path) -> Result<usize, String> {
+/// 检查外部 collections 目录：返回有效清单与逐个列出的无效清单；没有任何有效清单时返回错误。
+pub fn validate_collections_dir(dir: &Path) -> Result<CollectionListing, String> {
   if !dir.exists() {
     return Err(format!("directory not found: {}", dir.display()));
   }
-  let count = collect_manifest_files(dir).len();
-  if count == 0 {
+  let listing = collect_from_source(dir, "overlay");
+  if listing.manifests.is_empty() && listing.errors.is_empty() {
     return Err(format!("no manifest.json found in: {}", dir.display()));
   }
-  Ok(count)
+  if listing.manifests.is_empty() {
+    let details: Vec<String> = listing.errors.iter().map(describe_manifest_error).collect();
+    return Err(format!("no valid manifest.json in {}: {}", dir.display(), details.join("; ")));
+  }
+  Ok(listing)
+}
+
+/// 单行描述一个无效清单，JSON 错误附带行列。
+pub fn describe_manifest_error(e: &ManifestError) -> String {
+  match (e.line, e.column) {
+    (Some(line), Some(column)) => format!("[{}] {} (line {}, column {})", e.kind, e.path, line, column),
+    _ => format!("[{}] {}", e.kind, e.message),
+  }
+}
+
+/// 相对路径 -> 解析结果，按路径排序；无法解析的清单记入 `errors`，其相对路径记入 `broken`。
+fn parse_tree(
+  root: &Path,
+  errors: &mut Vec<ManifestError>,
+  broken: &mut HashSet<String>,
+) -> Result<BTreeMap<String, ParsedManifest>, String> {
+  if !root.is_dir() {
+    return Err(format!("directory not found: {}", root.display()));
+  }
+  let mut tree = BTreeMap::new();
+  for path in collect_manifest_files(root) {
+    let rel = relative_path(root, &path);
+    match check_manifest_file(&path) {
+      Ok(parsed) => {
+        tree.insert(rel, parsed);
+      }
+      Err(e) => {
+        errors.push(e);
+        broken.insert(rel);
+      }
+    }
+  }
+  Ok(tree)
+}
+
+/// `from` 中有、`to` 中没有的 ID，保持 `from` 的顺序。
+fn ids_missing_from(from: &[String], to: &[String]) -> Vec<String> {
+  let to: HashSet<&str> = to.iter().map(String::as_str).collect();
+  let mut seen = HashSet::new();
+  from
+    .iter()
+    .filter(|id| !to.contains(id.as_str()) && seen.insert(id.as_str()))
+    .cloned()
+    .collect()
+}
+
+fn same_ids(a: &ParsedManifest, b: &ParsedManifest) -> bool {
+  let a: HashSet<&String> = a.level_ids.iter().collect();
+  let b: HashSet<&String> = b.level_ids.iter().collect();
+  a == b
+}
+
+/// 比较两个 collections 目录：逐个清单列出新增、删除的 ID，以及清单本身的新增、删除、改名与移动。
+/// 无法解析的清单逐个列在 `errors` 中，不影响其余清单的比较。
+pub fn diff_collections(old_root: &Path, new_root: &Path) -> Result<CollectionDiff, String> {
+  let mut errors = Vec::new();
+  let mut broken = HashSet::new();
+  let mut old = parse_tree(old_root, &mut errors, &mut broken)?;
+  let mut new = parse_tree(new_root, &mut errors, &mut broken)?;
+  // 任一边无法解析的清单两边都不参与比较，以免被当作新增或删除。
+  old.retain(|rel, _| !broken.contains(rel));
+  new.retain(|rel, _| !broken.contains(rel));
+
+  let mut manifests = Vec::new();
+  let mut unchanged_count = 0usize;
+  for (rel, current) in &new {
+    let Some(previous) = old.get(rel) else {
+      continue;
+    };
+    let added_ids = ids_missing_from(&current.level_ids, &previous.level_ids);
+    let removed_ids = ids_missing_from(&previous.level_ids, &current.level_ids);
+    let renamed = current.name != previous.name;
+    if !renamed && added_ids.is_empty() && removed_ids.is_empty() {
+      unchanged_count += 1;
+      continue;
+    }
+    manifests.push(ManifestDiff {
+      relative_path: rel.clone(),
+      name: current.name.clone(),
+      status: if renamed { "renamed" } else { "changed" }.to_string(),
+      previous_name: renamed.then(|| previous.name.clone()),
+      previous_relative_path: None,
+      added_ids,
+      removed_ids,
+    });
+  }
+
+  // 只在一边出现的清单：ID 完全相同的视为移动，其余为新增或删除。
+  let mut gone: Vec<(&String, &ParsedManifest)> = old.iter().filter(|(rel, _)| !new.contains_key(*rel)).collect();
+  for (rel, current) in new.iter().filter(|(rel, _)| !old.contains_key(*rel)) {
+    let moved = gone.iter().position(|(_, previous)| same_ids(previous, current));
+    let diff = match moved.map(|i| gone.remove(i)) {
+      Some((previous_rel, previous)) => ManifestDiff {
+        relative_path: rel.clone(),
+        name: current.name.clone(),
+        status: "renamed".to_string(),
+        previous_name: (previous.name != current.name).then(|| previous.name.clone()),
+        previous_relative_path: Some(previous_rel.clone()),
+        added_ids: Vec::new(),
+        removed_ids: Vec::new(),
+      },
+      None => ManifestDiff {
+        relative_path: rel.clone(),
+        name: current.name.clone(),
+        status: "added".to_string(),
+        previous_name: None,
+        previous_relative_path: None,
+        added_ids: ids_missing_from(&current.level_ids, &[]),
+        removed_ids: Vec::new(),
+      },
+    };
+    manifests.push(diff);
+  }
+  for (rel, previous) in gone {
+    manifests.push(ManifestDiff {
+      relative_path: rel.clone(),
+      name: previous.name.clone(),
+      status: "removed".to_string(),
+      previous_name: None,
+      previous_relative_path: None,
+      added_ids: Vec::new(),
+      removed_ids: ids_missing_from(&previous.level_ids, &[]),
+    });
+  }
+  manifests.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+
+  let all_ids = |tree: &BTreeMap<String, ParsedManifest>| -> Vec<String> {
+    tree.values().flat_map(|m| m.level_ids.iter().cloned()).collect()
+  };
+  let (old_ids, new_ids) = (all_ids(&old), all_ids(&new));
+  Ok(CollectionDiff {
+    old_root: old_root.to_string_lossy().to_string(),
+    new_root: new_root.to_string_lossy().to_string(),
+    manifests,
+    unchanged_count,
+    added_ids: ids_missing_from(&new_ids, &old_ids),
+    removed_ids: ids_missing_from(&old_ids, &new_ids),
+    errors,
+  })
+}
+
+/// 由清单生成新清单时的集合运算。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOp {
+  Union,
+  Intersection,
+  /// 第一个清单减去其余清单。
+  Difference,
+}
+
+impl SetOp {
+  pub fn parse(value: &str) -> Result<Self, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+      "union" => Ok(Self::Union),
+      "intersection" | "intersect" => Ok(Self::Intersection),
+      "difference" | "diff" => Ok(Self::Difference),
+      other => Err(format!("unknown set operation: {}", other)),
+    }
+  }
+}
+
+/// 去重并保持第一次出现的顺序；交集与差集按第一个清单的顺序。
+pub fn combine_level_ids(op: SetOp, lists: &[Vec<String>]) -> Vec<String> {
+  let Some((first, rest)) = lists.split_first() else {
+    return Vec::new();
+  };
+  let keep = |id: &String| -> bool {
+    match op {
+      SetOp::Union => true,
+      SetOp::Intersection => rest.iter().all(|l| l.contains(id)),
+      SetOp::Difference => !rest.iter().any(|l| l.contains(id)),
+    }
+  };
+  let candidates: Vec<&String> = match op {
+    SetOp::Union => lists.iter().flatten().collect(),
+    _ => first.iter().filter(|id| keep(id)).collect(),
+  };
+  let mut seen = HashSet::new();
+  candidates
+    .into_iter()
+    .filter(|id| seen.insert(id.as_str()))
+    .cloned()
+    .collect()
+}
+
+/// 清单文件夹名只能是 `root` 下的一层，不能包含路径分隔符或 `..`。
+fn manifest_path_in(root: &Path, folder: &str) -> Result<PathBuf, String> {
+  let folder = folder.trim();
+  if folder.is_empty() || folder == "." || folder == ".." || folder.contains(['/', '\\']) {
+    return Err(format!("invalid collection folder name: {:?}", folder));
+  }
+  Ok(root.join(folder).join("manifest.json"))
+}
+
+/// 写入 `{name, levelIds}`：先写同目录临时文件并用 `parse_manifest_file` 校验，通过后再 rename。
+pub fn write_manifest(path: &Path, name: &str, level_ids: &[String]) -> Result<ParsedManifest, String> {
+  let name = name.trim();
+  if name.is_empty() {
+    return Err("manifest name is empty".to_string());
+  }
+  let mut seen = HashSet::new();
+  let level_ids: Vec<&str> = level_ids
+    .iter()
+    .map(|id| id.trim())
+    .filter(|id| !id.is_empty() && seen.insert(*id))
+    .collect();
+  let bytes = serde_json::to_vec_pretty(&serde_json::json!({ "name": name, "levelIds": level_ids }))
+    .map_err(|e| format!("serialize manifest failed: {}", e))?;
+
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).map_err(|e| format!("create dir failed {}: {}", parent.display(), e))?;
+  }
+  let tmp = fsutil::tmp_path_for(path);
+  let written = fs::write(&tmp, &bytes)
+    .map_err(|e| format!("write manifest failed {}: {}", tmp.display(), e))
+    .and_then(|_| parse_manifest_file(&tmp))
+    .and_then(|parsed| {
+      fs::rename(&tmp, path)
+        .map(|_| parsed)
+        .map_err(|e| format!("rename manifest failed {}: {}", path.display(), e))
+    });
+  if written.is_err() {
+    let _ = fs::remove_file(&tmp);
+  }
+  written
+}
+
+/// 在 `root/<folder>/manifest.json` 新建清单；已存在时报错。
+pub fn create_manifest(
+  root: &Path,
+  folder: &str,
+  name: &str,
+  level_ids: &[String],
+) -> Result<CollectionManifestMeta, String> {
+  let path = manifest_path_in(root, folder)?;
+  if path.exists() {
+    return Err(format!("manifest already exists: {}", path.display()));
+  }
+  let parsed = write_manifest(&path, name, level_ids)?;
+  Ok(manifest_meta(root, &path, "overlay", parsed))
+}
+
+/// 读取 `source` 清单，增删 ID（可改名）后写入 `target`；两者相同时原地修改，
+/// 内置清单则写到 overlay 中相同的相对路径，覆盖内置版本。
+pub fn update_manifest(
+  root: &Path,
+  source: &Path,
+  target: &Path,
+  add_ids: &[String],
+  remove_ids: &[String],
+  name: Option<&str>,
+) -> Result<CollectionManifestMeta, String> {
+  let current = parse_manifest_file(source)?;
+  let remove: HashSet<&str> = remove_ids.iter().map(|id| id.trim()).collect();
+  let level_ids: Vec<String> = current
+    .level_ids
+    .iter()
+    .chain(add_ids)
+    .filter(|id| !remove.contains(id.trim()))
+    .cloned()
+    .collect();
+  let name = name.filter(|n| !n.trim().is_empty()).unwrap_or(&current.name);
+  let parsed = write_manifest(target, name, &level_ids)?;
+  Ok(manifest_meta(root, target, "overlay", parsed))
+}
+
+/// 对已有清单做集合运算，结果写入 `root/<folder>/manifest.json`。
+pub fn combine_manifests(
+  root: &Path,
+  op: SetOp,
+  sources: &[PathBuf],
+  folder: &str,
+  name: &str,
+) -> Result<CollectionManifestMeta, String> {
+  if sources.is_empty() {
+    return Err("no source manifests".to_string());
+  }
+  let lists = sources
+    .iter()
+    .map(|p| parse_manifest_file(p).map(|m| m.level_ids))
+    .collect::<Result<Vec<_>, _>>()?;
+  let level_ids = combine_level_ids(op, &lists);
+  if level_ids.is_empty() {
+    return Err(format!("{:?} of the selected manifests is empty", op));
+  }
+  create_manifest(root, folder, name, &level_ids)
+}
+
+/// 把选中的清单导出为 `target_dir/collections/<文件夹>/manifest.json`，文件夹名取清单所在目录名。
+/// 给出 `local_dir` 时只保留该目录下已有谱面文件的 ID，裁剪后为空的清单不导出。
+pub fn export_collections(
+  manifest_paths: &[PathBuf],
+  target_dir: &Path,
+  local_dir: Option<&Path>,
+) -> Result<CollectionExportReport, String> {
+  if manifest_paths.is_empty() {
+    return Err("no manifests selected".to_string());
+  }
+  if let Some(dir) = local_dir.filter(|d| !d.is_dir()) {
+    return Err(format!("directory not found: {}", dir.display()));
+  }
+  let collections_dir = target_dir.join("collections");
+  let mut report = CollectionExportReport {
+    collections_dir: collections_dir.to_string_lossy().to_string(),
+    exported: Vec::new(),
+    skipped: Vec::new(),
+  };
+  let mut taken = HashSet::new();
+
+  for source in manifest_paths {
+    let parsed = parse_manifest_file(source)?;
+    let (level_ids, removed_ids): (Vec<String>, Vec<String>) = match local_dir {
+      Some(dir) => parsed
+        .level_ids
+        .iter()
+        .cloned()
+        .partition(|id| library::local_chart_file(dir, id).is_some()),
+      None => (parsed.level_ids.clone(), Vec::new()),
+    };
+    if level_ids.is_empty() {
+      report.skipped.push(FailItem {
+        id: source.to_string_lossy().to_string(),
+        reason: "no local charts".to_string(),
+      });
+      continue;
+    }
+
+    // 不同位置的同名文件夹依次改为 `<folder>_2`……
+    let base = source
+      .parent()
+      .and_then(|d| d.file_name())
+      .map(|s| sanitize_id_for_filename(&s.to_string_lossy()))
+      .unwrap_or_else(|| sanitize_id_for_filename(&parsed.name));
+    let folder = std::iter::once(base.clone())
+      .chain((2..).map(|n| format!("{}_{}", base, n)))
+      .find(|f| !taken.contains(&f.to_ascii_lowercase()))
+      .unwrap_or_default();
+    taken.insert(folder.to_ascii_lowercase());
+
+    let path = collections_dir.join(&folder).join("manifest.json");
+    let written = write_manifest(&path, &parsed.name, &level_ids)?;
+    report.exported.push(ExportedManifest {
+      source_path: source.to_string_lossy().to_string(),
+      folder,
+      name: written.name,
+      path: path.to_string_lossy().to_string(),
+      level_count: written.level_ids.len(),
+      removed_ids,
+    });
+  }
+  Ok(report)
 }
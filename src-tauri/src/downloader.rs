@@ -1,46 +1,35 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::path::PathBuf;
-use std::sync::{
-  atomic::{AtomicBool, Ordering},
-  Arc,
-};
+use std::sync::Arc;
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, Result};
 use chrono::Local;
-use futures_util::StreamExt;
+use futures_util::future::join_all;
+use futures_util::stream::{self, StreamExt};
+use parking_lot::Mutex;
 use reqwest::Client;
-use serde::Deserialize;
-use tauri::{AppHandle, Emitter};
 use tokio::fs;
-use tokio::io::AsyncWriteExt;
-use tokio::time::{sleep, Duration};
+use tokio::time::{sleep, sleep_until, Duration, Instant};
 
+use crate::archive::{self, ArchiveCheck, VerifyError};
 use crate::bundler;
+use crate::chart_index;
 use crate::collections;
+use crate::filter;
+use crate::library::{self, VariantRecord};
+use crate::maidata::ChartMeta;
 use crate::models::{DownloadTaskInput, FailItem, TaskEvent};
-use crate::{push_log, set_task_message, update_task, InnerState};
+use crate::source::{resolve_api_base, ChartSource, MilkbotSource};
+use crate::{persist_task, push_log, set_task_message, update_task, InnerState, TaskControl};
 
-const API_BASE: &str = "https://api.milkbot.cn/server/api";
+const DEFAULT_CONCURRENCY: usize = 3;
+const MAX_CONCURRENCY: usize = 16;
 
-#[derive(Debug, Deserialize)]
-struct VerifyResp {
-  success: bool,
-  key: Option<String>,
-  message: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
-struct LinkResp {
-  success: bool,
-  url: Option<String>,
-  message: Option<String>,
-}
-
-fn now_str() -> String {
+pub(crate) fn now_str() -> String {
   Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
 }
 
-fn truncate_for_log(input: &str, max_chars: usize) -> String {
+pub(crate) fn truncate_for_log(input: &str, max_chars: usize) -> String {
   let chars: Vec<char> = input.chars().collect();
   if chars.len() <= max_chars {
     return input.to_string();
@@ -65,7 +54,7 @@ fn mask_secret(input: &str) -> String {
   format!("len={} {}****{}", len, head, tail)
 }
 
-fn sanitize_id_for_filename(id: &str) -> String {
+pub fn sanitize_id_for_filename(id: &str) -> String {
   let mut normalized = String::with_capacity(id.len());
   for ch in id.chars() {
     let invalid = ch.is_control() || matches!(ch, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*');
@@ -94,7 +83,18 @@ fn sanitize_id_for_filename(id: &str) -> String {
   cleaned
 }
 
-fn emit_event(app: &AppHandle, task_id: &str, level: &str, event: &str, message: String, status: Option<String>) {
+/// 任务进度的输出端：GUI 下转发为 Tauri 事件，命令行下打印到终端。
+pub trait ProgressSink: Send + Sync {
+  fn emit(&self, event: TaskEvent);
+}
+
+impl<T: ProgressSink + ?Sized> ProgressSink for Arc<T> {
+  fn emit(&self, event: TaskEvent) {
+    (**self).emit(event);
+  }
+}
+
+pub fn emit_event(sink: &dyn ProgressSink, task_id: &str, level: &str, event: &str, message: String, status: Option<String>) {
   let payload = TaskEvent {
     task_id: task_id.to_string(),
     level: level.to_string(),
@@ -102,126 +102,7 @@ fn emit_event(app: &AppHandle, task_id: &str, level: &str, event: &str, message:
     message,
     status,
   };
-  let _ = app.emit("task_event", payload);
-}
-
-async fn verify_key(client: &Client, connect_sid: &str, code: &str) -> Result<String> {
-  let resp = client
-    .post(format!("{}/verify_captcha", API_BASE))
-    .header("Content-Type", "application/json")
-    .header("Cookie", format!("connect.sid={}", connect_sid))
-    .json(&serde_json::json!({ "code": code }))
-    .send()
-    .await
-    .context("verify_captcha request failed")?;
-
-  let status = resp.status();
-  let body = resp.text().await.context("verify_captcha read body failed")?;
-  let data: VerifyResp = serde_json::from_str(&body).with_context(|| {
-    format!(
-      "verify_captcha parse failed (status={}, body={})",
-      status,
-      truncate_for_log(&body, 300)
-    )
-  })?;
-
-  if !status.is_success() {
-    return Err(anyhow!(
-      "verify_captcha http {}: {}",
-      status,
-      data
-        .message
-        .unwrap_or_else(|| truncate_for_log(&body, 200))
-    ));
-  }
-
-  if data.success {
-    data.key.ok_or_else(|| anyhow!("verify_captcha success but key is empty"))
-  } else {
-    Err(anyhow!(
-      "verify_captcha failed: {}",
-      data.message.unwrap_or_else(|| "unknown".to_string())
-    ))
-  }
-}
-
-async fn get_download_link(
-  client: &Client,
-  connect_sid: &str,
-  key: &str,
-  id: &str,
-  kind: &str,
-) -> Result<String> {
-  let resp = client
-    .get(format!("{}/get_download_link", API_BASE))
-    .header("Cookie", format!("connect.sid={}", connect_sid))
-    .query(&[("id", id), ("key", key), ("type", kind)])
-    .send()
-    .await
-    .context("get_download_link request failed")?;
-
-  let status = resp.status();
-  let body = resp.text().await.context("get_download_link read body failed")?;
-  let data: LinkResp = serde_json::from_str(&body).with_context(|| {
-    format!(
-      "get_download_link parse failed (status={}, body={})",
-      status,
-      truncate_for_log(&body, 300)
-    )
-  })?;
-
-  if !status.is_success() {
-    return Err(anyhow!(
-      "get_download_link http {} for {}: {}",
-      status,
-      id,
-      data
-        .message
-        .unwrap_or_else(|| truncate_for_log(&body, 200))
-    ));
-  }
-
-  if data.success {
-    data.url.ok_or_else(|| anyhow!("get_download_link success but url is empty"))
-  } else {
-    Err(anyhow!(
-      "get_download_link failed for {}: {}",
-      id,
-      data.message.unwrap_or_else(|| "unknown".to_string())
-    ))
-  }
-}
-
-async fn download_file(client: &Client, url: &str, out_path: &PathBuf) -> Result<()> {
-  if let Some(parent) = out_path.parent() {
-    fs::create_dir_all(parent).await?;
-  }
-
-  let tmp = out_path.with_extension(format!(
-    "{}.part",
-    out_path.extension().and_then(|e| e.to_str()).unwrap_or("tmp")
-  ));
-
-  let resp = client
-    .get(url)
-    .send()
-    .await
-    .with_context(|| format!("download request failed: {}", url))?;
-
-  if !resp.status().is_success() {
-    return Err(anyhow!("download response status: {}", resp.status()));
-  }
-
-  let mut file = fs::File::create(&tmp).await?;
-  let mut stream = resp.bytes_stream();
-  while let Some(chunk) = stream.next().await {
-    let bytes = chunk?;
-    file.write_all(&bytes).await?;
-  }
-  file.flush().await?;
-  fs::rename(&tmp, out_path).await?;
-
-  Ok(())
+  sink.emit(payload);
 }
 
 async fn with_retry<T, F, Fut>(retries: u32, interval_ms: u64, mut f: F) -> Result<T>
@@ -245,10 +126,17 @@ where
   Err(last_err.unwrap_or_else(|| anyhow!("retry exhausted")))
 }
 
-fn merge_ids_from_manifests(paths: &[String]) -> Result<Vec<String>> {
+fn merge_ids_from_manifests(paths: &[String], extra_ids: &[String]) -> Result<Vec<String>> {
   let mut set = HashSet::new();
   let mut out = Vec::new();
 
+  for id in extra_ids {
+    let id = id.trim();
+    if !id.is_empty() && set.insert(id.to_string()) {
+      out.push(id.to_string());
+    }
+  }
+
   for p in paths {
     let path = PathBuf::from(p);
     let manifest = collections::parse_manifest_file(&path).map_err(|e| anyhow!(e))?;
@@ -263,42 +151,362 @@ fn merge_ids_from_manifests(paths: &[String]) -> Result<Vec<String>> {
   Ok(out)
 }
 
+/// 全局限速：所有 worker 共享，保证 get_download_link 请求之间至少间隔 interval。
+struct RateLimiter {
+  interval: Duration,
+  next_slot: tokio::sync::Mutex<Instant>,
+}
+
+impl RateLimiter {
+  fn new(interval_ms: u64) -> Self {
+    Self {
+      interval: Duration::from_millis(interval_ms),
+      next_slot: tokio::sync::Mutex::new(Instant::now()),
+    }
+  }
+
+  async fn acquire(&self) {
+    let slot = {
+      let mut next = self.next_slot.lock().await;
+      let slot = (*next).max(Instant::now());
+      *next = slot + self.interval;
+      slot
+    };
+    sleep_until(slot).await;
+  }
+}
+
+struct TaskContext {
+  sink: Arc<dyn ProgressSink>,
+  state: Arc<InnerState>,
+  task_id: String,
+  source: Arc<dyn ChartSource>,
+  connect_sid: String,
+  key: String,
+  kind: &'static str,
+  ext: &'static str,
+  output_dir: PathBuf,
+  retries: u32,
+  interval_ms: u64,
+  link_limiter: RateLimiter,
+  control: Arc<TaskControl>,
+  /// 本次新下载的 (level ID, 文件)。
+  new_files: Mutex<Vec<(String, PathBuf)>>,
+  upgrade_bga: bool,
+  /// 输出目录的 `chart_variants.json`；下载成功时只更新内存，全部 worker 结束后写回一次。
+  variants: Mutex<BTreeMap<String, VariantRecord>>,
+}
+
+impl TaskContext {
+  /// 没有下载记录的旧文件按包内是否有视频判断。
+  fn needs_upgrade(&self, id: &str, check: &ArchiveCheck) -> bool {
+    if !self.upgrade_bga {
+      return false;
+    }
+    match self.variants.lock().get(id) {
+      Some(record) => record.variant == "nobga",
+      None => !check.has_bga,
+    }
+  }
+
+  fn record_variant(&self, id: &str, out_path: &std::path::Path) {
+    let file_name = out_path
+      .file_name()
+      .map(|s| s.to_string_lossy().to_string())
+      .unwrap_or_default();
+    self.variants.lock().insert(
+      id.to_string(),
+      VariantRecord {
+        file_name,
+        variant: self.kind.to_string(),
+        downloaded_at: now_str(),
+      },
+    );
+  }
+
+  /// 本次有新下载时写回 `chart_variants.json`（取消时也会写，已完成的下载不会丢失记录）。
+  async fn save_variants(&self) {
+    if self.new_files.lock().is_empty() {
+      return;
+    }
+    let dir = self.output_dir.clone();
+    let variants = self.variants.lock().clone();
+    let saved = tokio::task::spawn_blocking(move || library::save_variants(&dir, &variants)).await;
+    let err = match saved {
+      Ok(Ok(())) => return,
+      Ok(Err(e)) => format!("{:#}", e),
+      Err(e) => e.to_string(),
+    };
+    push_log(&self.state, &self.task_id, format!("写入下载版本记录失败: {}", err));
+  }
+
+  fn record_fail(&self, id: &str, kind: &str, label: &str, err: &anyhow::Error) {
+    let reason = truncate_for_log(&err.to_string(), 280);
+    update_task(&self.state, &self.task_id, |t| {
+      t.fail_count += 1;
+      t.processed_ids += 1;
+      t.fail_items.push(FailItem {
+        id: id.to_string(),
+        reason: format!("{}: {}", kind, reason),
+      });
+    });
+    let line = format!("FAIL {}: {} | {}", id, label, reason);
+    push_log(&self.state, &self.task_id, line.clone());
+    emit_event(&self.sink, &self.task_id, "error", "fail", line, Some("running".to_string()));
+  }
+}
+
+async fn process_id(ctx: &TaskContext, id: &str) {
+  let safe_id = sanitize_id_for_filename(id);
+  let out_path = ctx.output_dir.join(format!("{}.{}", safe_id, ctx.ext));
+
+  let mut upgrading = false;
+  if let Ok(meta) = fs::metadata(&out_path).await {
+    let check_path = out_path.clone();
+    let check = if meta.len() > 0 {
+      tokio::task::spawn_blocking(move || archive::verify_chart_archive(&check_path, false).ok())
+        .await
+        .unwrap_or(None)
+    } else {
+      None
+    };
+    match check {
+      Some(check) if !ctx.needs_upgrade(id, &check) => {
+        update_task(&ctx.state, &ctx.task_id, |t| {
+          t.skip_count += 1;
+          t.processed_ids += 1;
+        });
+        let line = format!("SKIP {}", id);
+        push_log(&ctx.state, &ctx.task_id, line.clone());
+        emit_event(&ctx.sink, &ctx.task_id, "info", "skip", line, Some("running".to_string()));
+        return;
+      }
+      // 新文件校验通过后才会 rename 覆盖旧文件；下载失败时旧文件保持原样。
+      Some(_) => {
+        upgrading = true;
+        let line = format!("UPGRADE {}: 本地为无 BGA 版本，重新下载 BGA 版本", id);
+        push_log(&ctx.state, &ctx.task_id, line.clone());
+        emit_event(&ctx.sink, &ctx.task_id, "info", "upgrade", line, Some("running".to_string()));
+      }
+      None => {
+        let line = format!("REDOWNLOAD {}: 本地文件为空或已损坏", id);
+        push_log(&ctx.state, &ctx.task_id, line.clone());
+        emit_event(&ctx.sink, &ctx.task_id, "warn", "redownload", line, Some("running".to_string()));
+        let _ = fs::remove_file(&out_path).await;
+      }
+    }
+  }
+
+  let link_result = with_retry(ctx.retries, ctx.interval_ms, |_: u32| async move {
+    ctx.link_limiter.acquire().await;
+    ctx.source.resolve_link(&ctx.connect_sid, &ctx.key, id, ctx.kind).await
+  })
+  .await;
+
+  let url = match link_result {
+    Ok(v) => v,
+    Err(e) => {
+      ctx.record_fail(id, "link_fail", "获取下载链接失败", &e);
+      return;
+    }
+  };
+
+  let download_result = with_retry(ctx.retries, ctx.interval_ms, |_: u32| {
+    let url = &url;
+    let out = &out_path;
+    async move { ctx.source.fetch(url, out).await }
+  })
+  .await;
+
+  match download_result {
+    Ok(_) => {
+      ctx.new_files.lock().push((id.to_string(), out_path.clone()));
+      ctx.record_variant(id, &out_path);
+      update_task(&ctx.state, &ctx.task_id, |t| {
+        t.ok_count += 1;
+        t.new_files_count += 1;
+        t.processed_ids += 1;
+        if upgrading {
+          t.upgraded_count += 1;
+        }
+      });
+      let line = if upgrading {
+        format!("OK {} (已升级为 BGA 版本)", id)
+      } else {
+        format!("OK {}", id)
+      };
+      push_log(&ctx.state, &ctx.task_id, line.clone());
+      emit_event(&ctx.sink, &ctx.task_id, "info", "ok", line, Some("running".to_string()));
+    }
+    Err(e) if e.downcast_ref::<VerifyError>().is_some() => ctx.record_fail(id, "verify_fail", "文件校验失败", &e),
+    Err(e) => ctx.record_fail(id, "download_fail", "下载失败", &e),
+  }
+}
+
+async fn run_worker(ctx: &TaskContext, queue: &Mutex<VecDeque<String>>) {
+  loop {
+    if ctx.control.is_cancelled() || queue.lock().is_empty() {
+      return;
+    }
+    // 暂停只阻止领取新的 ID，进行中的下载会照常完成。
+    if ctx.control.is_paused() {
+      ctx.control.wait_while_paused().await;
+      continue;
+    }
+    let next = queue.lock().pop_front();
+    let Some(id) = next else {
+      return;
+    };
+    process_id(ctx, &id).await;
+  }
+}
+
+/// 元数据先取输出目录下的谱面索引，索引中没有的再向 source 查询；查不到的 ID 保留。
+#[allow(clippy::too_many_arguments)]
+async fn filter_ids(
+  sink: &Arc<dyn ProgressSink>,
+  state: &Arc<InnerState>,
+  task_id: &str,
+  input: &DownloadTaskInput,
+  source: &dyn ChartSource,
+  expr: &str,
+  ids: Vec<String>,
+  concurrency: usize,
+) -> Result<Vec<String>, String> {
+  let rules = filter::parse_filter(expr)?;
+
+  let output_dir = PathBuf::from(&input.output_dir);
+  let index = tokio::task::spawn_blocking(move || chart_index::load_index(&output_dir))
+    .await
+    .unwrap_or_default();
+  let mut metas: HashMap<String, ChartMeta> = HashMap::new();
+  for id in &ids {
+    if let Some(entry) = index.entries.get(id) {
+      metas.insert(id.clone(), entry.meta.clone());
+    }
+  }
+
+  let missing: Vec<String> = ids.iter().filter(|id| !metas.contains_key(*id)).cloned().collect();
+  let fetched: Vec<(String, Result<Option<ChartMeta>>)> = stream::iter(missing)
+    .map(|id| async move {
+      let res = source.chart_metadata(&id).await;
+      (id, res)
+    })
+    .buffer_unordered(concurrency)
+    .collect()
+    .await;
+  for (id, res) in fetched {
+    match res {
+      Ok(Some(meta)) => {
+        metas.insert(id, meta);
+      }
+      Ok(None) => {}
+      Err(e) => {
+        push_log(state, task_id, format!("[FILTER] {} 元数据查询失败: {}", id, e));
+      }
+    }
+  }
+
+  let (kept, report) = filter::apply_filter(expr, ids, &rules, &metas);
+  for stat in &report.rules {
+    push_log(state, task_id, format!("[FILTER] 规则 {} 筛掉 {} 个", stat.rule, stat.removed));
+  }
+  let summary = format!(
+    "过滤后保留 {}/{}（{} 个无元数据，按保留处理）",
+    report.kept_count, report.input_count, report.unknown_count
+  );
+  emit_event(sink, task_id, "info", "filter", summary.clone(), Some("running".to_string()));
+  push_log(state, task_id, summary.clone());
+  update_task(state, task_id, |t| t.filter_report = Some(report));
+  Ok(kept)
+}
+
 pub async fn run_task(
-  app: AppHandle,
+  sink: Arc<dyn ProgressSink>,
   state: Arc<InnerState>,
   task_id: String,
   input: DownloadTaskInput,
-  cancel_flag: Arc<AtomicBool>,
+  control: Arc<TaskControl>,
+) {
+  let client = match Client::builder().build() {
+    Ok(c) => c,
+    Err(e) => {
+      set_task_message(&state, &task_id, "failed", format!("HTTP 客户端初始化失败: {}", e));
+      emit_event(&sink, &task_id, "error", "fatal", format!("HTTP 客户端初始化失败: {}", e), Some("failed".to_string()));
+      return;
+    }
+  };
+  let base = resolve_api_base(input.api_base.as_deref());
+  let source: Arc<dyn ChartSource> =
+    Arc::new(MilkbotSource::new(client, &base).with_metadata_url(input.metadata_url.as_deref()));
+  run_task_with_source(sink, state, task_id, input, control, source).await;
+}
+
+/// 与 run_task 相同，但由调用方提供谱面服务端（镜像或测试替身）。
+pub async fn run_task_with_source(
+  sink: Arc<dyn ProgressSink>,
+  state: Arc<InnerState>,
+  task_id: String,
+  input: DownloadTaskInput,
+  control: Arc<TaskControl>,
+  source: Arc<dyn ChartSource>,
 ) {
   let retries = input.retries.unwrap_or(3).max(1);
   let interval_ms = input.request_interval_ms.unwrap_or(1000);
+  let concurrency = input
+    .concurrency
+    .unwrap_or(DEFAULT_CONCURRENCY)
+    .clamp(1, MAX_CONCURRENCY);
+  if input.upgrade_bga && input.download_no_bga {
+    let line = "升级模式需要下载 BGA 版本，不能同时选择不含 BGA".to_string();
+    set_task_message(&state, &task_id, "failed", line.clone());
+    emit_event(&sink, &task_id, "error", "fatal", line, Some("failed".to_string()));
+    return;
+  }
+  let bundle_options = match bundler::BundleOptions::from_params(
+    input.bundle_conflict_policy.as_deref(),
+    input.bundle_max_bytes,
+    input.bundle_max_charts,
+  ) {
+    Ok(v) => v,
+    Err(e) => {
+      set_task_message(&state, &task_id, "failed", format!("整合参数无效: {}", e));
+      emit_event(&sink, &task_id, "error", "fatal", format!("整合参数无效: {}", e), Some("failed".to_string()));
+      return;
+    }
+  };
 
   update_task(&state, &task_id, |t| {
     t.status = "running".to_string();
     t.started_at = Some(now_str());
     t.message = Some("任务启动".to_string());
   });
-  emit_event(&app, &task_id, "info", "start", format!("任务启动: {}", task_id), Some("running".to_string()));
-
-  let client = Client::builder().build();
-  let client = match client {
-    Ok(c) => c,
-    Err(e) => {
-      set_task_message(&state, &task_id, "failed", format!("HTTP 客户端初始化失败: {}", e));
-      emit_event(&app, &task_id, "error", "fatal", format!("HTTP 客户端初始化失败: {}", e), Some("failed".to_string()));
-      return;
-    }
-  };
+  emit_event(&sink, &task_id, "info", "start", format!("任务启动: {}", task_id), Some("running".to_string()));
 
-  let merged_ids = match merge_ids_from_manifests(&input.selected_manifest_paths) {
+  let merged_ids = match merge_ids_from_manifests(
+    &input.selected_manifest_paths,
+    input.level_ids.as_deref().unwrap_or_default(),
+  ) {
     Ok(v) => v,
     Err(e) => {
       set_task_message(&state, &task_id, "failed", format!("读取 manifest 失败: {}", e));
-      emit_event(&app, &task_id, "error", "fatal", format!("读取 manifest 失败: {}", e), Some("failed".to_string()));
+      emit_event(&sink, &task_id, "error", "fatal", format!("读取 manifest 失败: {}", e), Some("failed".to_string()));
       return;
     }
   };
 
+  let merged_ids = match input.filter.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+    Some(expr) => match filter_ids(&sink, &state, &task_id, &input, source.as_ref(), expr, merged_ids, concurrency).await {
+      Ok(v) => v,
+      Err(e) => {
+        set_task_message(&state, &task_id, "failed", format!("过滤表达式无效: {}", e));
+        emit_event(&sink, &task_id, "error", "fatal", format!("过滤表达式无效: {}", e), Some("failed".to_string()));
+        return;
+      }
+    },
+    None => merged_ids,
+  };
+
   update_task(&state, &task_id, |t| {
     t.total_ids = merged_ids.len();
     t.message = Some(format!("合并后待下载 ID 数: {}", merged_ids.len()));
@@ -306,7 +514,7 @@ pub async fn run_task(
 
   if merged_ids.is_empty() {
     set_task_message(&state, &task_id, "failed", "没有可下载的 ID".to_string());
-    emit_event(&app, &task_id, "error", "fatal", "没有可下载的 ID".to_string(), Some("failed".to_string()));
+    emit_event(&sink, &task_id, "error", "fatal", "没有可下载的 ID".to_string(), Some("failed".to_string()));
     return;
   }
 
@@ -315,7 +523,7 @@ pub async fn run_task(
       Some(k) if !k.trim().is_empty() => k,
       _ => {
         set_task_message(&state, &task_id, "failed", "auth_mode=key 但 key 为空".to_string());
-        emit_event(&app, &task_id, "error", "fatal", "auth_mode=key 但 key 为空".to_string(), Some("failed".to_string()));
+        emit_event(&sink, &task_id, "error", "fatal", "auth_mode=key 但 key 为空".to_string(), Some("failed".to_string()));
         return;
       }
     }
@@ -324,19 +532,19 @@ pub async fn run_task(
       Some(c) if !c.trim().is_empty() => c,
       _ => {
         set_task_message(&state, &task_id, "failed", "auth_mode=captcha 但验证码为空".to_string());
-        emit_event(&app, &task_id, "error", "fatal", "auth_mode=captcha 但验证码为空".to_string(), Some("failed".to_string()));
+        emit_event(&sink, &task_id, "error", "fatal", "auth_mode=captcha 但验证码为空".to_string(), Some("failed".to_string()));
         return;
       }
     };
 
-    match verify_key(&client, &input.connect_sid, &code).await {
+    match source.verify(&input.connect_sid, &code).await {
       Ok(k) => {
-        emit_event(&app, &task_id, "info", "auth", "验证码校验成功，已获取 key".to_string(), Some("running".to_string()));
+        emit_event(&sink, &task_id, "info", "auth", "验证码校验成功，已获取 key".to_string(), Some("running".to_string()));
         k
       }
       Err(e) => {
         set_task_message(&state, &task_id, "failed", format!("验证码校验失败: {}", e));
-        emit_event(&app, &task_id, "error", "fatal", format!("验证码校验失败: {}", e), Some("failed".to_string()));
+        emit_event(&sink, &task_id, "error", "fatal", format!("验证码校验失败: {}", e), Some("failed".to_string()));
         return;
       }
     }
@@ -345,12 +553,12 @@ pub async fn run_task(
   let output_dir = PathBuf::from(input.output_dir.clone());
   if let Err(e) = fs::create_dir_all(&output_dir).await {
     set_task_message(&state, &task_id, "failed", format!("输出目录不可写: {}", e));
-    emit_event(&app, &task_id, "error", "fatal", format!("输出目录不可写: {}", e), Some("failed".to_string()));
+    emit_event(&sink, &task_id, "error", "fatal", format!("输出目录不可写: {}", e), Some("failed".to_string()));
     return;
   }
 
   let kind = if input.download_no_bga { "nobga" } else { "bga" };
-  let ext = if input.output_format.to_ascii_lowercase() == "zip" {
+  let ext = if input.output_format.eq_ignore_ascii_case("zip") {
     "zip"
   } else {
     "adx"
@@ -360,118 +568,57 @@ pub async fn run_task(
     &state,
     &task_id,
     format!(
-      "任务参数: auth_mode={}, connect.sid={}, key={}, type={}, format={}, retries={}, interval_ms={}, output_dir={}",
+      "任务参数: auth_mode={}, connect.sid={}, key={}, type={}, upgrade_bga={}, format={}, retries={}, interval_ms={}, concurrency={}, api={}, output_dir={}",
       input.auth_mode,
       mask_secret(&input.connect_sid),
       mask_secret(&key),
       kind,
+      input.upgrade_bga,
       ext,
       retries,
       interval_ms,
+      concurrency,
+      source.endpoint(),
       input.output_dir
     ),
   );
 
-  let mut new_files: Vec<PathBuf> = Vec::new();
-
-  for id in merged_ids {
-    if cancel_flag.load(Ordering::Relaxed) {
-      set_task_message(&state, &task_id, "cancelled", "任务已取消".to_string());
-      emit_event(&app, &task_id, "warn", "cancelled", "任务已取消".to_string(), Some("cancelled".to_string()));
-      return;
-    }
-
-    let safe_id = sanitize_id_for_filename(&id);
-    let out_path = output_dir.join(format!("{}.{}", safe_id, ext));
-
-    let processed_delta = 1usize;
-    if let Ok(meta) = fs::metadata(&out_path).await {
-      if meta.len() > 0 {
-        update_task(&state, &task_id, |t| {
-          t.skip_count += 1;
-          t.processed_ids += processed_delta;
-        });
-        let line = format!("SKIP {}", id);
-        push_log(&state, &task_id, line.clone());
-        emit_event(&app, &task_id, "info", "skip", line, Some("running".to_string()));
-        sleep(Duration::from_millis(interval_ms)).await;
-        continue;
-      }
-    }
-
-    let link_result = with_retry(retries, interval_ms, |_: u32| {
-      let client = client.clone();
-      let sid = input.connect_sid.clone();
-      let key = key.clone();
-      let id = id.clone();
-      async move { get_download_link(&client, &sid, &key, &id, kind).await }
-    })
-    .await;
-
-    let url = match link_result {
-      Ok(v) => v,
-      Err(e) => {
-        let reason = truncate_for_log(&e.to_string(), 280);
-        update_task(&state, &task_id, |t| {
-          t.fail_count += 1;
-          t.processed_ids += processed_delta;
-          t.fail_items.push(FailItem {
-            id: id.clone(),
-            reason: format!("link_fail: {}", reason),
-          });
-        });
-        let line = format!("FAIL {}: 获取下载链接失败 | {}", id, reason);
-        push_log(&state, &task_id, line.clone());
-        emit_event(&app, &task_id, "error", "fail", line, Some("running".to_string()));
-        sleep(Duration::from_millis(interval_ms)).await;
-        continue;
-      }
-    };
-
-    let download_result = with_retry(retries, interval_ms, |_: u32| {
-      let client = client.clone();
-      let out = out_path.clone();
-      let url = url.clone();
-      async move { download_file(&client, &url, &out).await }
-    })
-    .await;
+  let ctx = TaskContext {
+    sink: sink.clone(),
+    state: state.clone(),
+    task_id: task_id.clone(),
+    source,
+    connect_sid: input.connect_sid.clone(),
+    key,
+    kind,
+    ext,
+    output_dir: output_dir.clone(),
+    retries,
+    interval_ms,
+    link_limiter: RateLimiter::new(interval_ms),
+    control: control.clone(),
+    new_files: Mutex::new(Vec::new()),
+    upgrade_bga: input.upgrade_bga,
+    variants: Mutex::new(library::load_variants(&output_dir)),
+  };
+  let queue = Mutex::new(merged_ids.into_iter().collect::<VecDeque<_>>());
 
-    match download_result {
-      Ok(_) => {
-        new_files.push(out_path.clone());
-        update_task(&state, &task_id, |t| {
-          t.ok_count += 1;
-          t.new_files_count += 1;
-          t.processed_ids += processed_delta;
-        });
-        let line = format!("OK {}", id);
-        push_log(&state, &task_id, line.clone());
-        emit_event(&app, &task_id, "info", "ok", line, Some("running".to_string()));
-      }
-      Err(e) => {
-        let reason = truncate_for_log(&e.to_string(), 280);
-        update_task(&state, &task_id, |t| {
-          t.fail_count += 1;
-          t.processed_ids += processed_delta;
-          t.fail_items.push(FailItem {
-            id: id.clone(),
-            reason: format!("download_fail: {}", reason),
-          });
-        });
-        let line = format!("FAIL {}: 下载失败 | {}", id, reason);
-        push_log(&state, &task_id, line.clone());
-        emit_event(&app, &task_id, "error", "fail", line, Some("running".to_string()));
-      }
-    }
+  join_all((0..concurrency).map(|_| run_worker(&ctx, &queue))).await;
+  ctx.save_variants().await;
 
-    sleep(Duration::from_millis(interval_ms)).await;
+  if control.is_cancelled() {
+    set_task_message(&state, &task_id, "cancelled", "任务已取消".to_string());
+    emit_event(&sink, &task_id, "warn", "cancelled", "任务已取消".to_string(), Some("cancelled".to_string()));
+    return;
   }
 
+  let new_files = ctx.new_files.into_inner();
+
   if input.auto_bundle {
     if new_files.is_empty() {
       let line = "自动整合已跳过: 本次无新增下载文件".to_string();
       push_log(&state, &task_id, line.clone());
-      emit_event(&app, &task_id, "info", "bundle_skip", line, Some("running".to_string()));
+      emit_event(&sink, &task_id, "info", "bundle_skip", line, Some("running".to_string()));
     } else {
       let output = input.bundle_output_path.clone().unwrap_or_else(|| {
         output_dir
@@ -481,36 +628,59 @@ pub async fn run_task(
       });
 
       let output_path = PathBuf::from(output.clone());
+      let append = input.bundle_append && output_path.is_file();
       emit_event(
-        &app,
+        &sink,
         &task_id,
         "info",
         "bundle_start",
-        "开始自动整合（仅本次新下载文件）".to_string(),
+        if append {
+          format!("开始追加到已有整合包: {}", output)
+        } else {
+          "开始自动整合（仅本次新下载文件）".to_string()
+        },
         Some("running".to_string()),
       );
 
-      let bundle_result = tauri::async_runtime::spawn_blocking(move || {
-        bundler::build_bundle_from_files(&new_files, &output_path)
+      let bundle_options = bundler::BundleOptions {
+        level_ids: new_files.iter().map(|(id, path)| (path.clone(), id.clone())).collect(),
+        ..bundle_options
+      };
+      let new_files: Vec<PathBuf> = new_files.into_iter().map(|(_, path)| path).collect();
+      let bundle_result = tokio::task::spawn_blocking(move || {
+        if append {
+          bundler::append_to_bundle(&output_path, &new_files, &bundle_options, &())
+        } else {
+          bundler::build_bundle_from_files(&new_files, &output_path, &bundle_options)
+        }
       })
       .await;
 
       match bundle_result {
         Ok(Ok(summary)) => {
           update_task(&state, &task_id, |t| {
-            t.bundle_output_path = Some(summary.output_path.clone());
+            t.bundle_output_paths = summary.output_paths.clone();
           });
+          for conflict in &summary.conflicts {
+            let line = format!("[BUNDLE CONFLICT] {}", conflict.describe());
+            push_log(&state, &task_id, line.clone());
+            emit_event(&sink, &task_id, "warn", "bundle_conflict", line, Some("running".to_string()));
+          }
           let line = format!(
-            "自动整合完成: {}（源文件 {}，处理 {}）",
-            summary.output_path, summary.source_file_count, summary.processed_count
+            "自动整合完成: {}（源文件 {}，写入 {}，已存在 {}，冲突 {}）",
+            summary.output_paths.join(", "),
+            summary.source_file_count,
+            summary.added.len(),
+            summary.already_present.len(),
+            summary.conflicts.len()
           );
           push_log(&state, &task_id, line.clone());
-          emit_event(&app, &task_id, "info", "bundle_done", line, Some("running".to_string()));
+          emit_event(&sink, &task_id, "info", "bundle_done", line, Some("running".to_string()));
         }
         Ok(Err(e)) => {
           set_task_message(&state, &task_id, "failed", format!("自动整合失败: {}", e));
           emit_event(
-            &app,
+            &sink,
             &task_id,
             "error",
             "fatal",
@@ -522,7 +692,7 @@ pub async fn run_task(
         Err(e) => {
           set_task_message(&state, &task_id, "failed", format!("自动整合任务异常: {}", e));
           emit_event(
-            &app,
+            &sink,
             &task_id,
             "error",
             "fatal",
@@ -540,9 +710,10 @@ pub async fn run_task(
     t.ended_at = Some(now_str());
     t.message = Some("任务完成".to_string());
   });
+  persist_task(&state, &task_id);
 
   emit_event(
-    &app,
+    &sink,
     &task_id,
     "info",
     "done",
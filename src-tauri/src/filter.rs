@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::maidata::{level_value, ChartMeta};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CmpOp {
+  Lt,
+  Le,
+  Eq,
+  Ge,
+  Gt,
+}
+
+impl CmpOp {
+  fn eval(self, lhs: f64, rhs: f64) -> bool {
+    match self {
+      CmpOp::Lt => lhs < rhs,
+      CmpOp::Le => lhs <= rhs,
+      CmpOp::Eq => (lhs - rhs).abs() < 1e-9,
+      CmpOp::Ge => lhs >= rhs,
+      CmpOp::Gt => lhs > rhs,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Predicate {
+  /// 指定难度存在谱面。
+  Has(String),
+  /// 任一（或指定）难度的定级满足比较。
+  Level {
+    difficulty: Option<String>,
+    op: CmpOp,
+    value: f64,
+  },
+  Bga,
+}
+
+/// 过滤表达式中的一条规则，例如 `remaster`、`!utage`、`level>=13`、`master.level>=13+`、`bga`。
+#[derive(Debug, Clone)]
+pub struct FilterRule {
+  pub text: String,
+  negate: bool,
+  predicate: Predicate,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterRuleStat {
+  pub rule: String,
+  pub removed: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterReport {
+  pub expression: String,
+  pub input_count: usize,
+  pub kept_count: usize,
+  /// 没有元数据、无法判断而保留的 ID 数。
+  pub unknown_count: usize,
+  pub rules: Vec<FilterRuleStat>,
+}
+
+/// 把难度写法统一成 maidata 中的名字：`Re:MASTER`、`remas`、`宴` 等。
+pub fn normalize_difficulty(name: &str) -> Option<&'static str> {
+  let key: String = name
+    .chars()
+    .filter(|c| !matches!(c, ':' | ' ' | '_' | '-'))
+    .collect::<String>()
+    .to_lowercase();
+  let out = match key.as_str() {
+    "easy" => "easy",
+    "basic" | "bas" => "basic",
+    "advanced" | "adv" => "advanced",
+    "expert" | "exp" => "expert",
+    "master" | "mas" => "master",
+    "remaster" | "remas" | "rem" => "remaster",
+    "utage" | "宴" => "utage",
+    _ => return None,
+  };
+  Some(out)
+}
+
+fn parse_level_rule(lhs: &str, op: CmpOp, rhs: &str, raw: &str) -> Result<Predicate, String> {
+  let difficulty = match lhs.trim().to_lowercase().as_str() {
+    "level" | "lv" => None,
+    other => {
+      let name = other
+        .strip_suffix(".level")
+        .or_else(|| other.strip_suffix(".lv"))
+        .ok_or_else(|| format!("unknown filter field: {}", raw))?;
+      Some(
+        normalize_difficulty(name)
+          .ok_or_else(|| format!("unknown difficulty in filter: {}", raw))?
+          .to_string(),
+      )
+    }
+  };
+  let value = level_value(rhs).ok_or_else(|| format!("invalid level in filter: {}", raw))?;
+  Ok(Predicate::Level { difficulty, op, value })
+}
+
+fn parse_rule(raw: &str) -> Result<FilterRule, String> {
+  let (negate, body) = match raw.strip_prefix('!') {
+    Some(rest) => (true, rest.trim()),
+    None => (false, raw),
+  };
+
+  const OPS: [(&str, CmpOp); 5] = [
+    (">=", CmpOp::Ge),
+    ("<=", CmpOp::Le),
+    (">", CmpOp::Gt),
+    ("<", CmpOp::Lt),
+    ("=", CmpOp::Eq),
+  ];
+  let mut predicate = None;
+  for (token, op) in OPS {
+    if let Some((lhs, rhs)) = body.split_once(token) {
+      predicate = Some(parse_level_rule(lhs, op, rhs, raw)?);
+      break;
+    }
+  }
+
+  let predicate = match predicate {
+    Some(p) => p,
+    None => {
+      let name = body.strip_prefix("has:").unwrap_or(body);
+      if name.eq_ignore_ascii_case("bga") {
+        Predicate::Bga
+      } else {
+        let diff = normalize_difficulty(name).ok_or_else(|| format!("unknown filter rule: {}", raw))?;
+        Predicate::Has(diff.to_string())
+      }
+    }
+  };
+
+  Ok(FilterRule {
+    text: raw.to_string(),
+    negate,
+    predicate,
+  })
+}
+
+/// 规则之间以空白、逗号或分号分隔，全部满足才保留。
+pub fn parse_filter(expr: &str) -> Result<Vec<FilterRule>, String> {
+  expr
+    .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+    .filter(|s| !s.is_empty())
+    .map(parse_rule)
+    .collect()
+}
+
+impl FilterRule {
+  /// 元数据不足以判断时返回 None。
+  pub fn matches(&self, meta: &ChartMeta) -> Option<bool> {
+    let hit = match &self.predicate {
+      Predicate::Has(name) => meta.difficulty(name).is_some_and(|d| d.has_chart),
+      Predicate::Level { difficulty, op, value } => meta
+        .difficulties
+        .iter()
+        .filter(|d| d.has_chart)
+        .filter(|d| difficulty.as_deref().is_none_or(|name| d.name == name))
+        .filter_map(|d| d.level.as_deref().and_then(level_value))
+        .any(|lv| op.eval(lv, *value)),
+      Predicate::Bga => meta.has_bga?,
+    };
+    Some(hit != self.negate)
+  }
+}
+
+/// 按顺序应用规则；每个 ID 记在第一条淘汰它的规则上。
+pub fn apply_filter(
+  expression: &str,
+  ids: Vec<String>,
+  rules: &[FilterRule],
+  metas: &HashMap<String, ChartMeta>,
+) -> (Vec<String>, FilterReport) {
+  let mut report = FilterReport {
+    expression: expression.to_string(),
+    input_count: ids.len(),
+    rules: rules
+      .iter()
+      .map(|r| FilterRuleStat {
+        rule: r.text.clone(),
+        removed: 0,
+      })
+      .collect(),
+    ..FilterReport::default()
+  };
+
+  let mut kept = Vec::with_capacity(ids.len());
+  for id in ids {
+    let Some(meta) = metas.get(&id) else {
+      report.unknown_count += 1;
+      kept.push(id);
+      continue;
+    };
+    let rejected_by = rules.iter().position(|r| r.matches(meta) == Some(false));
+    match rejected_by {
+      Some(i) => report.rules[i].removed += 1,
+      None => kept.push(id),
+    }
+  }
+
+  report.kept_count = kept.len();
+  (kept, report)
+}
@@ -0,0 +1,32 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// 先写入同目录下的临时文件再 rename，保证读者看不到写了一半的内容。
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).with_context(|| format!("create parent dir failed: {}", parent.display()))?;
+  }
+  let tmp = tmp_path_for(path);
+  fs::write(&tmp, bytes).with_context(|| format!("write temp file failed: {}", tmp.display()))?;
+  if let Err(e) = fs::rename(&tmp, path) {
+    let _ = fs::remove_file(&tmp);
+    return Err(e).with_context(|| format!("rename temp file failed: {}", path.display()));
+  }
+  Ok(())
+}
+
+pub fn tmp_path_for(path: &Path) -> PathBuf {
+  let mut s = path.as_os_str().to_os_string();
+  s.push(format!(".{}.tmp", uuid::Uuid::new_v4().simple()));
+  PathBuf::from(s)
+}
+
+/// 两个路径是否指向同一文件；无法解析（如尚不存在）时按原样比较。
+pub fn same_file(a: &Path, b: &Path) -> bool {
+  match (fs::canonicalize(a), fs::canonicalize(b)) {
+    (Ok(a), Ok(b)) => a == b,
+    _ => a == b,
+  }
+}
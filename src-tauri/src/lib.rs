@@ -1,32 +1,100 @@
-mod bundler;
-mod collections;
-mod downloader;
-mod models;
+#[cfg(feature = "gui")]
+mod app;
+pub mod archive;
+pub mod bundle_index;
+pub mod bundle_task;
+pub mod bundler;
+pub mod chart_index;
+pub mod collections;
+pub mod downloader;
+pub mod filter;
+mod fsutil;
+pub mod library;
+pub mod maidata;
+pub mod models;
+pub mod overlay_watch;
+pub mod source;
+pub mod task_store;
 
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::{
-  atomic::AtomicBool,
+  atomic::{AtomicBool, Ordering},
   Arc,
 };
 
 use parking_lot::Mutex;
-use tauri::Manager;
+use tokio::sync::Notify;
 
-use models::{CollectionManifestMeta, DownloadTaskInput, TaskState};
+use models::{DownloadTaskInput, TaskRecord, TaskState};
+use task_store::TaskStore;
+
+#[cfg(feature = "gui")]
+pub use app::{run, TauriProgressSink};
+
+/// 运行中任务的控制句柄：取消、暂停与恢复。
+#[derive(Default)]
+pub struct TaskControl {
+  cancelled: AtomicBool,
+  paused: AtomicBool,
+  wake: Notify,
+}
+
+impl TaskControl {
+  pub fn cancel(&self) {
+    self.cancelled.store(true, Ordering::Relaxed);
+    self.wake.notify_waiters();
+  }
+
+  pub fn pause(&self) {
+    self.paused.store(true, Ordering::Relaxed);
+  }
+
+  pub fn resume(&self) {
+    self.paused.store(false, Ordering::Relaxed);
+    self.wake.notify_waiters();
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.cancelled.load(Ordering::Relaxed)
+  }
+
+  pub fn is_paused(&self) -> bool {
+    self.paused.load(Ordering::Relaxed)
+  }
+
+  /// 暂停期间挂起，直到恢复或取消。
+  pub async fn wait_while_paused(&self) {
+    loop {
+      // 先登记等待再检查状态，避免错过 resume/cancel 的通知。
+      let notified = self.wake.notified();
+      if !self.is_paused() || self.is_cancelled() {
+        return;
+      }
+      notified.await;
+    }
+  }
+}
 
 pub struct InnerState {
   pub tasks: Mutex<HashMap<String, TaskState>>,
-  pub cancel_flags: Mutex<HashMap<String, Arc<AtomicBool>>>,
+  pub task_inputs: Mutex<HashMap<String, DownloadTaskInput>>,
+  pub task_controls: Mutex<HashMap<String, Arc<TaskControl>>>,
   pub overlay_collections_dir: Mutex<Option<PathBuf>>,
+  /// 当前外部 collections 目录的监视；替换时旧的随之停止。
+  pub overlay_watcher: Mutex<Option<overlay_watch::OverlayWatcher>>,
+  pub task_store: Mutex<Option<TaskStore>>,
 }
 
 impl Default for InnerState {
   fn default() -> Self {
     Self {
       tasks: Mutex::new(HashMap::new()),
-      cancel_flags: Mutex::new(HashMap::new()),
+      task_inputs: Mutex::new(HashMap::new()),
+      task_controls: Mutex::new(HashMap::new()),
       overlay_collections_dir: Mutex::new(None),
+      overlay_watcher: Mutex::new(None),
+      task_store: Mutex::new(None),
     }
   }
 }
@@ -40,13 +108,12 @@ impl Default for AppRuntimeState {
   }
 }
 
-pub fn update_task<F>(state: &Arc<InnerState>, task_id: &str, f: F)
+/// 在同一次加锁中读取并修改任务状态；任务不存在时返回 None。
+pub fn update_task<F, R>(state: &Arc<InnerState>, task_id: &str, f: F) -> Option<R>
 where
-  F: FnOnce(&mut TaskState),
+  F: FnOnce(&mut TaskState) -> R,
 {
-  if let Some(task) = state.tasks.lock().get_mut(task_id) {
-    f(task);
-  }
+  state.tasks.lock().get_mut(task_id).map(f)
 }
 
 pub fn push_log(state: &Arc<InnerState>, task_id: &str, line: String) {
@@ -66,114 +133,22 @@ pub fn set_task_message(state: &Arc<InnerState>, task_id: &str, status: &str, me
     t.ended_at = Some(chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
   });
   push_log(state, task_id, message);
+  persist_task(state, task_id);
 }
 
-#[tauri::command]
-async fn list_builtin_collections(
-  app: tauri::AppHandle,
-  state: tauri::State<'_, AppRuntimeState>,
-) -> Result<Vec<CollectionManifestMeta>, String> {
-  let overlay = state.0.overlay_collections_dir.lock().clone();
-  collections::list_collections(&app, overlay)
-}
-
-#[derive(serde::Serialize)]
-#[serde(rename_all = "camelCase")]
-struct RefreshResult {
-  manifest_count: usize,
-  dir: String,
-}
-
-#[tauri::command]
-async fn refresh_collections_from_dir(
-  dir: String,
-  state: tauri::State<'_, AppRuntimeState>,
-) -> Result<RefreshResult, String> {
-  let path = PathBuf::from(dir.trim());
-  let count = collections::validate_collections_dir(&path)?;
-  *state.0.overlay_collections_dir.lock() = Some(path.clone());
-  Ok(RefreshResult {
-    manifest_count: count,
-    dir: path.to_string_lossy().to_string(),
-  })
-}
-
-#[derive(serde::Serialize)]
-#[serde(rename_all = "camelCase")]
-struct StartTaskResult {
-  task_id: String,
-}
-
-#[tauri::command]
-async fn start_download_task(
-  app: tauri::AppHandle,
-  state: tauri::State<'_, AppRuntimeState>,
-  input: DownloadTaskInput,
-) -> Result<StartTaskResult, String> {
-  if input.selected_manifest_paths.is_empty() {
-    return Err("selectedManifestPaths is empty".to_string());
+/// 把任务当前状态写入任务历史；未配置存储（或任务不存在）时什么也不做。
+pub fn persist_task(state: &Arc<InnerState>, task_id: &str) {
+  let store = state.task_store.lock();
+  let Some(store) = store.as_ref() else {
+    return;
+  };
+  let Some(task) = state.tasks.lock().get(task_id).cloned() else {
+    return;
+  };
+  let Some(input) = state.task_inputs.lock().get(task_id).map(task_store::redact_input) else {
+    return;
+  };
+  if let Err(e) = store.save(&TaskRecord { input, state: task }) {
+    eprintln!("persist task {} failed: {:#}", task_id, e);
   }
-
-  let task_id = uuid::Uuid::new_v4().to_string();
-  let task = TaskState::new(task_id.clone());
-  let cancel_flag = Arc::new(AtomicBool::new(false));
-
-  state.0.tasks.lock().insert(task_id.clone(), task);
-  state
-    .0
-    .cancel_flags
-    .lock()
-    .insert(task_id.clone(), cancel_flag.clone());
-
-  let state_arc = state.0.clone();
-  let app_handle = app.clone();
-  let task_id_for_run = task_id.clone();
-
-  tauri::async_runtime::spawn(async move {
-    downloader::run_task(app_handle, state_arc, task_id_for_run, input, cancel_flag).await;
-  });
-
-  Ok(StartTaskResult { task_id })
-}
-
-#[tauri::command]
-async fn cancel_task(
-  task_id: String,
-  state: tauri::State<'_, AppRuntimeState>,
-) -> Result<(), String> {
-  let map = state.0.cancel_flags.lock();
-  if let Some(flag) = map.get(&task_id) {
-    flag.store(true, std::sync::atomic::Ordering::Relaxed);
-    Ok(())
-  } else {
-    Err(format!("task not found: {}", task_id))
-  }
-}
-
-#[tauri::command]
-async fn get_task_state(
-  task_id: String,
-  state: tauri::State<'_, AppRuntimeState>,
-) -> Result<Option<TaskState>, String> {
-  Ok(state.0.tasks.lock().get(&task_id).cloned())
-}
-
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-  tauri::Builder::default()
-    .plugin(tauri_plugin_dialog::init())
-    .manage(AppRuntimeState::default())
-    .invoke_handler(tauri::generate_handler![
-      list_builtin_collections,
-      refresh_collections_from_dir,
-      start_download_task,
-      cancel_task,
-      get_task_state
-    ])
-    .setup(|app| {
-      let _ = app.get_webview_window("main");
-      Ok(())
-    })
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
 }
@@ -0,0 +1,289 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::archive;
+use crate::bundle_index;
+use crate::collections;
+use crate::downloader::sanitize_id_for_filename;
+use crate::fsutil;
+
+pub const CHART_EXTENSIONS: [&str; 2] = ["adx", "zip"];
+/// 各谱面下载的是哪个版本，与谱面放在同一个输出目录中。
+pub const VARIANTS_FILE_NAME: &str = "chart_variants.json";
+
+/// 一个本地谱面文件的下载版本。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VariantRecord {
+  pub file_name: String,
+  /// bga / nobga，即下载时的 `type`。
+  pub variant: String,
+  pub downloaded_at: String,
+}
+
+/// level ID -> 下载版本；文件缺失或无法解析时为空。
+pub fn load_variants(output_dir: &Path) -> BTreeMap<String, VariantRecord> {
+  fs::read(output_dir.join(VARIANTS_FILE_NAME))
+    .ok()
+    .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+    .unwrap_or_default()
+}
+
+pub fn save_variants(output_dir: &Path, variants: &BTreeMap<String, VariantRecord>) -> Result<()> {
+  let bytes = serde_json::to_vec_pretty(variants)?;
+  fsutil::write_atomic(&output_dir.join(VARIANTS_FILE_NAME), &bytes)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryFile {
+  pub file_name: String,
+  pub path: String,
+  /// 无法从文件名还原时为空。
+  pub level_id: Option<String>,
+  pub size: u64,
+  /// ok / empty / corrupt
+  pub status: String,
+  pub problem: Option<String>,
+  /// bga / nobga：优先取下载记录，没有记录但包内有视频时为 bga，否则为空。
+  pub variant: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestCoverage {
+  pub path: String,
+  pub name: String,
+  pub total: usize,
+  pub present: usize,
+  pub missing_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryScanReport {
+  pub output_dir: String,
+  pub files: Vec<LibraryFile>,
+  pub ok_count: usize,
+  pub empty_count: usize,
+  pub corrupt_count: usize,
+  /// 未完成下载留下的 `.part` / `.part.meta`，以及中断的原子写入留下的 `*.tmp`。
+  pub stale_parts: Vec<String>,
+  /// 之前生成的整合包（带 `bundle_index.json`），不当作谱面统计。
+  pub bundles: Vec<String>,
+  pub manifests: Vec<ManifestCoverage>,
+  /// 所有清单缺失 ID 的并集，可直接作为下载任务的 `levelIds`。
+  pub missing_ids: Vec<String>,
+}
+
+/// 按文件名找回 level ID：优先匹配清单中的 ID，否则文件名本身就是合法 ID 时直接采用。
+fn level_id_for_stem(stem: &str, known: &HashMap<String, String>) -> Option<String> {
+  if let Some(id) = known.get(stem) {
+    return Some(id.clone());
+  }
+  if sanitize_id_for_filename(stem) == stem {
+    return Some(stem.to_string());
+  }
+  None
+}
+
+fn inspect_file(path: &Path, size: u64) -> (String, Option<String>, bool) {
+  if size == 0 {
+    return ("empty".to_string(), Some("zero-byte file".to_string()), false);
+  }
+  match archive::verify_chart_archive(path, false) {
+    Ok(check) => ("ok".to_string(), None, check.has_bga),
+    Err(e) => ("corrupt".to_string(), Some(e.to_string()), false),
+  }
+}
+
+pub fn scan_library(output_dir: &Path, manifest_paths: &[String]) -> Result<LibraryScanReport> {
+  if !output_dir.is_dir() {
+    return Err(anyhow!("directory not found: {}", output_dir.display()));
+  }
+
+  let mut manifests = Vec::new();
+  let mut known: HashMap<String, String> = HashMap::new();
+  for p in manifest_paths {
+    let path = PathBuf::from(p);
+    let parsed = collections::parse_manifest_file(&path).map_err(|e| anyhow!(e))?;
+    for id in &parsed.level_ids {
+      known.insert(sanitize_id_for_filename(id), id.clone());
+    }
+    manifests.push((p.clone(), parsed));
+  }
+
+  let variants = load_variants(output_dir);
+  let mut files = Vec::new();
+  let mut stale_parts = Vec::new();
+  let mut bundles = Vec::new();
+  let entries = fs::read_dir(output_dir).with_context(|| format!("read dir failed: {}", output_dir.display()))?;
+  for entry in entries.flatten() {
+    let path = entry.path();
+    let Ok(meta) = entry.metadata() else {
+      continue;
+    };
+    if !meta.is_file() {
+      continue;
+    }
+    let file_name = entry.file_name().to_string_lossy().to_string();
+    let ext = path
+      .extension()
+      .and_then(|e| e.to_str())
+      .map(|e| e.to_ascii_lowercase())
+      .unwrap_or_default();
+
+    if ext == "part" || ext == "tmp" || file_name.ends_with(".part.meta") {
+      stale_parts.push(path.to_string_lossy().to_string());
+      continue;
+    }
+    if !CHART_EXTENSIONS.contains(&ext.as_str()) {
+      continue;
+    }
+    if bundle_index::is_bundle(&path) {
+      bundles.push(path.to_string_lossy().to_string());
+      continue;
+    }
+
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let (status, problem, has_bga) = inspect_file(&path, meta.len());
+    let level_id = level_id_for_stem(stem, &known);
+    let variant = level_id
+      .as_ref()
+      .and_then(|id| variants.get(id))
+      .filter(|r| r.file_name == file_name)
+      .map(|r| r.variant.clone())
+      .or_else(|| has_bga.then(|| "bga".to_string()));
+    files.push(LibraryFile {
+      file_name,
+      path: path.to_string_lossy().to_string(),
+      level_id,
+      size: meta.len(),
+      status,
+      problem,
+      variant,
+    });
+  }
+  files.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+  stale_parts.sort();
+  bundles.sort();
+
+  let present: BTreeSet<&str> = files
+    .iter()
+    .filter(|f| f.status == "ok")
+    .filter_map(|f| f.level_id.as_deref())
+    .collect();
+
+  let mut missing_all = BTreeSet::new();
+  let manifests = manifests
+    .into_iter()
+    .map(|(path, parsed)| {
+      let missing_ids: Vec<String> = parsed
+        .level_ids
+        .iter()
+        .filter(|id| !present.contains(id.as_str()))
+        .cloned()
+        .collect();
+      missing_all.extend(missing_ids.iter().cloned());
+      ManifestCoverage {
+        path,
+        name: parsed.name,
+        total: parsed.level_ids.len(),
+        present: parsed.level_ids.len() - missing_ids.len(),
+        missing_ids,
+      }
+    })
+    .collect();
+
+  let count = |status: &str| files.iter().filter(|f| f.status == status).count();
+  Ok(LibraryScanReport {
+    output_dir: output_dir.to_string_lossy().to_string(),
+    ok_count: count("ok"),
+    empty_count: count("empty"),
+    corrupt_count: count("corrupt"),
+    files,
+    stale_parts,
+    bundles,
+    manifests,
+    missing_ids: missing_all.into_iter().collect(),
+  })
+}
+
+/// 输出目录下某个 level ID 对应的谱面文件（`.adx` 优先）。
+pub fn local_chart_file(dir: &Path, id: &str) -> Option<PathBuf> {
+  let stem = sanitize_id_for_filename(id);
+  CHART_EXTENSIONS
+    .iter()
+    .map(|ext| dir.join(format!("{}.{}", stem, ext)))
+    .find(|p| p.is_file())
+}
+
+/// 目录下（不递归）全部谱面文件，按文件名排序；之前生成的整合包（带 `bundle_index.json`）不算谱面。
+pub fn list_chart_files(dir: &Path) -> Result<Vec<PathBuf>> {
+  let entries = fs::read_dir(dir).with_context(|| format!("read dir failed: {}", dir.display()))?;
+  let mut files: Vec<PathBuf> = entries
+    .flatten()
+    .map(|e| e.path())
+    .filter(|p| p.is_file())
+    .filter(|p| {
+      p.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| CHART_EXTENSIONS.iter().any(|c| e.eq_ignore_ascii_case(c)))
+    })
+    .filter(|p| !bundle_index::is_bundle(p))
+    .collect();
+  files.sort();
+  Ok(files)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedCharts {
+  pub files: Vec<PathBuf>,
+  /// 清单中有、但目录下找不到文件的 ID。
+  pub missing_ids: Vec<String>,
+  /// 由清单 ID 找到的文件 -> level ID。
+  pub level_ids: HashMap<PathBuf, String>,
+}
+
+/// 解析整合来源：给了清单时取清单 ID 在 `dir` 下对应的文件，否则取 `dir` 下全部谱面；
+/// `files` 中显式列出的文件追加在后面。
+pub fn resolve_chart_files(dir: Option<&Path>, files: &[String], manifest_paths: &[String]) -> Result<ResolvedCharts> {
+  let mut out = ResolvedCharts::default();
+
+  if !manifest_paths.is_empty() {
+    let dir = dir.ok_or_else(|| anyhow!("sourceDir is required when bundling manifests"))?;
+    let mut seen = BTreeSet::new();
+    for p in manifest_paths {
+      let parsed = collections::parse_manifest_file(Path::new(p)).map_err(|e| anyhow!(e))?;
+      for id in parsed.level_ids {
+        if !seen.insert(id.clone()) {
+          continue;
+        }
+        match local_chart_file(dir, &id) {
+          Some(path) => {
+            out.level_ids.insert(path.clone(), id);
+            out.files.push(path);
+          }
+          None => out.missing_ids.push(id),
+        }
+      }
+    }
+  } else if let Some(dir) = dir {
+    out.files = list_chart_files(dir)?;
+  }
+
+  for f in files {
+    let path = PathBuf::from(f);
+    if !path.is_file() {
+      return Err(anyhow!("file not found: {}", path.display()));
+    }
+    if !out.files.contains(&path) {
+      out.files.push(path);
+    }
+  }
+  Ok(out)
+}
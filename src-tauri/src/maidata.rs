@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+
+/// maidata.txt 中 `_N` 后缀对应的难度名。
+pub const DIFFICULTY_NAMES: [&str; 7] = ["easy", "basic", "advanced", "expert", "master", "remaster", "utage"];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DifficultyMeta {
+  /// maidata 中的序号（1..=7）。
+  pub index: u8,
+  pub name: String,
+  pub level: Option<String>,
+  pub designer: Option<String>,
+  pub has_chart: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ChartMeta {
+  pub title: Option<String>,
+  pub artist: Option<String>,
+  pub designer: Option<String>,
+  pub first: Option<f64>,
+  pub wholebpm: Option<String>,
+  pub difficulties: Vec<DifficultyMeta>,
+  /// 是否带 BGA 视频；来自压缩包内容而非 maidata，未知时为空。
+  pub has_bga: Option<bool>,
+}
+
+impl ChartMeta {
+  pub fn difficulty(&self, name: &str) -> Option<&DifficultyMeta> {
+    self.difficulties.iter().find(|d| d.name.eq_ignore_ascii_case(name))
+  }
+
+  /// 所有难度的谱师（含全局 `&des`），去重后按出现顺序返回。
+  pub fn designers(&self) -> Vec<&str> {
+    let mut out: Vec<&str> = Vec::new();
+    let all = self
+      .designer
+      .iter()
+      .chain(self.difficulties.iter().filter_map(|d| d.designer.as_ref()));
+    for d in all {
+      if !out.contains(&d.as_str()) {
+        out.push(d.as_str());
+      }
+    }
+    out
+  }
+}
+
+/// 把 `13+`、`13.7`、`14?` 之类的定级换算成可比较的数值；`+` 视为 .5。
+pub fn level_value(level: &str) -> Option<f64> {
+  let trimmed = level.trim().trim_end_matches('?');
+  if let Some(base) = trimmed.strip_suffix('+') {
+    return base.trim().parse::<f64>().ok().map(|v| v + 0.5);
+  }
+  trimmed.parse().ok()
+}
+
+fn non_empty(value: &str) -> Option<String> {
+  let v = value.trim();
+  if v.is_empty() {
+    None
+  } else {
+    Some(v.to_string())
+  }
+}
+
+/// 拆分 `&key=value` 字段；值可以跨行，直到下一行以 `&` 开头。
+fn split_fields(text: &str) -> Vec<(String, String)> {
+  let mut out: Vec<(String, String)> = Vec::new();
+  for line in text.lines() {
+    if let Some(rest) = line.strip_prefix('&') {
+      if let Some((k, v)) = rest.split_once('=') {
+        out.push((k.trim().to_ascii_lowercase(), v.to_string()));
+        continue;
+      }
+    }
+    if let Some((_, v)) = out.last_mut() {
+      v.push('\n');
+      v.push_str(line);
+    }
+  }
+  out
+}
+
+pub fn parse_maidata(text: &str) -> ChartMeta {
+  let text = text.trim_start_matches('\u{feff}');
+  let mut meta = ChartMeta::default();
+  let mut difficulties: Vec<DifficultyMeta> = DIFFICULTY_NAMES
+    .iter()
+    .enumerate()
+    .map(|(i, name)| DifficultyMeta {
+      index: (i + 1) as u8,
+      name: name.to_string(),
+      ..DifficultyMeta::default()
+    })
+    .collect();
+
+  for (key, value) in split_fields(text) {
+    match key.as_str() {
+      "title" => meta.title = non_empty(&value),
+      "artist" => meta.artist = non_empty(&value),
+      "des" => meta.designer = non_empty(&value),
+      "first" => meta.first = value.trim().parse().ok(),
+      "wholebpm" => meta.wholebpm = non_empty(&value),
+      _ => {
+        let Some((field, n)) = key.rsplit_once('_') else {
+          continue;
+        };
+        let Some(slot) = n
+          .parse::<usize>()
+          .ok()
+          .and_then(|n| n.checked_sub(1))
+          .and_then(|i| difficulties.get_mut(i))
+        else {
+          continue;
+        };
+        match field {
+          "lv" => slot.level = non_empty(&value),
+          "des" => slot.designer = non_empty(&value),
+          "inote" => slot.has_chart = !value.trim().is_empty(),
+          _ => {}
+        }
+      }
+    }
+  }
+
+  meta.difficulties = difficulties
+    .into_iter()
+    .filter(|d| d.has_chart || d.level.is_some())
+    .collect();
+  meta
+}
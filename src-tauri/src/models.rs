@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::filter::FilterReport;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CollectionManifestMeta {
@@ -11,6 +13,94 @@ pub struct CollectionManifestMeta {
   pub source: String,
 }
 
+/// 无法解析的 manifest.json。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestError {
+  pub path: String,
+  /// io / json / missing_level_ids / empty（文件为空或 `levelIds` 中没有有效 ID）
+  pub kind: String,
+  pub message: String,
+  /// JSON 错误所在的行与列（从 1 开始）。
+  pub line: Option<usize>,
+  pub column: Option<usize>,
+}
+
+/// 清单列表：有效的清单与逐个列出的无效清单。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionListing {
+  pub manifests: Vec<CollectionManifestMeta>,
+  pub errors: Vec<ManifestError>,
+}
+
+/// `collections_changed` 事件：外部 collections 目录中有清单被新增、修改或删除。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionsChanged {
+  pub root: String,
+  /// 新增或修改后仍然有效的清单。
+  pub changed: Vec<CollectionManifestMeta>,
+  /// 被删除的 manifest.json 路径。
+  pub removed: Vec<String>,
+  /// 新增或修改后无法解析的清单；其余清单不受影响。
+  pub errors: Vec<ManifestError>,
+}
+
+/// 两个 collections 目录中同一清单的差异；清单按相对路径对应。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestDiff {
+  pub relative_path: String,
+  pub name: String,
+  /// added / removed / changed / renamed（改了 `name` 或移动了位置，`levelIds` 也可能有变化）
+  pub status: String,
+  pub previous_name: Option<String>,
+  /// 移动前的相对路径（`levelIds` 完全相同的清单换了位置）。
+  pub previous_relative_path: Option<String>,
+  pub added_ids: Vec<String>,
+  pub removed_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedManifest {
+  pub source_path: String,
+  /// `collections/` 下的文件夹名。
+  pub folder: String,
+  pub name: String,
+  pub path: String,
+  pub level_count: usize,
+  /// 按本地谱面裁剪时去掉的 ID。
+  pub removed_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionExportReport {
+  /// 导出的 `collections/` 目录，可整个复制到 AstroDX。
+  pub collections_dir: String,
+  pub exported: Vec<ExportedManifest>,
+  /// 未导出的清单（`id` 为清单路径）及原因，例如裁剪后为空。
+  pub skipped: Vec<FailItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionDiff {
+  pub old_root: String,
+  pub new_root: String,
+  /// 只列出有变化的清单。
+  pub manifests: Vec<ManifestDiff>,
+  pub unchanged_count: usize,
+  /// 新目录中出现、旧目录任何清单都没有的 ID，可直接作为下载任务的 `levelIds`。
+  pub added_ids: Vec<String>,
+  /// 旧目录中有、新目录任何清单都没有的 ID。
+  pub removed_ids: Vec<String>,
+  /// 两个目录中无法解析的清单；它们不参与比较。
+  pub errors: Vec<ManifestError>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DownloadTaskInput {
@@ -24,8 +114,50 @@ pub struct DownloadTaskInput {
   pub output_format: String,
   pub auto_bundle: bool,
   pub bundle_output_path: Option<String>,
+  /// 整合时同名条目的处理方式：skip / rename（默认）/ keep_newest。
+  pub bundle_conflict_policy: Option<String>,
+  /// 分包：每个整合包的大致字节上限。
+  pub bundle_max_bytes: Option<u64>,
+  /// 分包：每个整合包最多包含的谱面数。
+  pub bundle_max_charts: Option<usize>,
+  /// `bundle_output_path` 已存在时，只把其中没有的谱面追加进去，而不是重新生成。
+  #[serde(default)]
+  pub bundle_append: bool,
+  /// 已有文件是无 BGA 版本时重新下载 BGA 版本并原子替换；需要 `download_no_bga` 为 false。
+  #[serde(default)]
+  pub upgrade_bga: bool,
   pub retries: Option<u32>,
   pub request_interval_ms: Option<u64>,
+  pub concurrency: Option<usize>,
+  /// 直接指定的 level ID，与 manifest 中的 ID 合并去重（例如重试失败项）。
+  pub level_ids: Option<Vec<String>>,
+  /// 覆盖下载站 API 地址（镜像或本地替身）；为空时读取 NICONICO_API_BASE。
+  pub api_base: Option<String>,
+  /// 按谱面元数据筛选，例如 `remaster level>=13 !utage`；见 filter.rs。
+  pub filter: Option<String>,
+  /// 过滤用的元数据接口；本地谱面索引中没有的 ID 才会查询。
+  pub metadata_url: Option<String>,
+}
+
+/// 独立整合任务：来源为目录、文件列表或清单（清单 ID 在 `source_dir` 下查找）。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleTaskInput {
+  pub source_dir: Option<String>,
+  pub files: Option<Vec<String>>,
+  pub manifest_paths: Option<Vec<String>>,
+  /// 为空时在来源目录下生成 `bundle_merged_<ts>.adx`。
+  pub output_path: Option<String>,
+  pub conflict_policy: Option<String>,
+  pub max_bytes: Option<u64>,
+  pub max_charts: Option<usize>,
+  /// 每个清单各生成一个整合包，并附带 `collections/<文件夹>/manifest.json`；
+  /// 此时 `output_path` 为输出目录。
+  #[serde(default)]
+  pub per_manifest: bool,
+  /// `output_path` 指向已有整合包时，只追加其中没有的谱面（不支持分包与按清单整合）。
+  #[serde(default)]
+  pub append: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,12 +178,24 @@ pub struct TaskState {
   pub skip_count: usize,
   pub fail_count: usize,
   pub new_files_count: usize,
-  pub bundle_output_path: Option<String>,
+  /// 升级模式下由无 BGA 版本替换为 BGA 版本的谱面数（也计入 ok_count）。
+  #[serde(default)]
+  pub upgraded_count: usize,
+  /// 独立整合任务中已写入整合包的谱面数（下载任务为 0；整合任务不使用 ok_count 等下载计数）。
+  #[serde(default)]
+  pub bundled_count: usize,
+  /// 整合包路径；分包时为全部分包。
+  #[serde(default)]
+  pub bundle_output_paths: Vec<String>,
   pub fail_items: Vec<FailItem>,
   pub logs: Vec<String>,
   pub started_at: Option<String>,
   pub ended_at: Option<String>,
   pub message: Option<String>,
+  /// 由 retry_failed_items 创建时，指向原任务 ID。
+  pub retry_of: Option<String>,
+  /// 设置了 filter 时，记录每条规则筛掉的数量。
+  pub filter_report: Option<FilterReport>,
 }
 
 impl TaskState {
@@ -65,16 +209,28 @@ impl TaskState {
       skip_count: 0,
       fail_count: 0,
       new_files_count: 0,
-      bundle_output_path: None,
+      upgraded_count: 0,
+      bundled_count: 0,
+      bundle_output_paths: Vec::new(),
       fail_items: Vec::new(),
       logs: Vec::new(),
       started_at: None,
       ended_at: None,
       message: None,
+      retry_of: None,
+      filter_report: None,
     }
   }
 }
 
+/// 持久化到任务历史中的一条记录；`input` 已脱敏。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskRecord {
+  pub input: DownloadTaskInput,
+  pub state: TaskState,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TaskEvent {
@@ -0,0 +1,142 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use notify_debouncer_mini::notify::RecursiveMode;
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::collections;
+use crate::models::CollectionsChanged;
+
+pub const COLLECTIONS_CHANGED_EVENT: &str = "collections_changed";
+/// 编辑器保存时常常连续写几次，合并这段时间内的事件。
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// 外部 collections 目录下已知的 manifest.json，用于把目录级事件（删除、移动）还原成具体清单。
+#[derive(Debug, Clone, Default)]
+pub struct KnownManifests {
+  paths: BTreeSet<PathBuf>,
+}
+
+impl KnownManifests {
+  pub fn scan(root: &Path) -> Self {
+    Self {
+      paths: collections::collect_manifest_files(root).into_iter().collect(),
+    }
+  }
+
+  /// 按文件系统事件涉及的路径重新解析受影响的清单；没有清单受影响时返回 None。
+  pub fn apply(&mut self, root: &Path, event_paths: &[PathBuf]) -> Option<CollectionsChanged> {
+    let mut touched = BTreeSet::new();
+    let mut removed = BTreeSet::new();
+    for path in event_paths {
+      if path.is_dir() {
+        // 移入或复制进来的目录；已知清单的修改另有文件级事件，不必重新解析。
+        let added = collections::collect_manifest_files(path);
+        touched.extend(added.into_iter().filter(|p| !self.paths.contains(p)));
+      } else if path.file_name().is_some_and(|n| n == "manifest.json") {
+        if path.is_file() {
+          touched.insert(path.clone());
+        } else {
+          removed.insert(path.clone());
+        }
+      }
+      // 被删除或移走的目录：其中原有的清单都已不在。
+      if !path.exists() {
+        removed.extend(self.paths.iter().filter(|p| p.starts_with(path)).cloned());
+      }
+    }
+
+    let mut changed = Vec::new();
+    let mut errors = Vec::new();
+    for path in touched {
+      removed.remove(&path);
+      self.paths.insert(path.clone());
+      match collections::check_manifest_file(&path) {
+        Ok(parsed) => changed.push(collections::manifest_meta(root, &path, "overlay", parsed)),
+        Err(e) => errors.push(e),
+      }
+    }
+    for path in &removed {
+      self.paths.remove(path);
+    }
+
+    if changed.is_empty() && removed.is_empty() && errors.is_empty() {
+      return None;
+    }
+    Some(CollectionsChanged {
+      root: root.to_string_lossy().to_string(),
+      changed,
+      removed: removed.iter().map(|p| p.to_string_lossy().to_string()).collect(),
+      errors,
+    })
+  }
+}
+
+/// 正在运行的监视；`stop` 或 drop 时关闭停止通道，监视任务随即退出。
+pub struct OverlayWatcher {
+  _stop: oneshot::Sender<()>,
+}
+
+impl OverlayWatcher {
+  pub fn stop(self) {
+    drop(self);
+  }
+}
+
+/// 递归监视 `root`，清单有变化时调用 `on_change`。需在 tokio 运行时中调用。
+pub fn spawn_watcher<F>(root: PathBuf, on_change: F) -> Result<OverlayWatcher>
+where
+  F: Fn(CollectionsChanged) + Send + 'static,
+{
+  let (tx, mut rx) = mpsc::unbounded_channel::<DebounceEventResult>();
+  let mut debouncer = new_debouncer(DEBOUNCE, move |res| {
+    let _ = tx.send(res);
+  })
+  .context("create file watcher failed")?;
+  debouncer
+    .watcher()
+    .watch(&root, RecursiveMode::Recursive)
+    .with_context(|| format!("watch directory failed: {}", root.display()))?;
+
+  let (stop_tx, mut stop_rx) = oneshot::channel();
+  tokio::spawn(async move {
+    // debouncer 随任务一起结束，停止后不再产生事件。
+    let _debouncer = debouncer;
+    let scan_root = root.clone();
+    let mut known = tokio::task::spawn_blocking(move || KnownManifests::scan(&scan_root))
+      .await
+      .unwrap_or_default();
+    loop {
+      let events = tokio::select! {
+        _ = &mut stop_rx => return,
+        received = rx.recv() => match received {
+          Some(Ok(events)) => events,
+          Some(Err(e)) => {
+            eprintln!("overlay watcher error: {}", e);
+            continue;
+          }
+          None => return,
+        },
+      };
+      let paths: Vec<PathBuf> = events.into_iter().map(|e| e.path).collect();
+      let root = root.clone();
+      let applied = tokio::task::spawn_blocking(move || {
+        let change = known.apply(&root, &paths);
+        (known, change)
+      })
+      .await;
+      let Ok((next, change)) = applied else {
+        return;
+      };
+      known = next;
+      if let Some(change) = change {
+        on_change(change);
+      }
+    }
+  });
+
+  Ok(OverlayWatcher { _stop: stop_tx })
+}
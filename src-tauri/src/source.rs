@@ -0,0 +1,418 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use futures_util::future::BoxFuture;
+use futures_util::StreamExt;
+use reqwest::{header, Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use crate::archive::{self, VerifyError};
+use crate::downloader::truncate_for_log;
+use crate::maidata::ChartMeta;
+
+pub const DEFAULT_API_BASE: &str = "https://api.milkbot.cn/server/api";
+
+/// 谱面服务端：换取 key、解析下载链接、下载文件。
+pub trait ChartSource: Send + Sync {
+  /// 用于日志展示的服务端地址。
+  fn endpoint(&self) -> &str;
+
+  fn verify<'a>(&'a self, connect_sid: &'a str, code: &'a str) -> BoxFuture<'a, Result<String>>;
+
+  fn resolve_link<'a>(
+    &'a self,
+    connect_sid: &'a str,
+    key: &'a str,
+    id: &'a str,
+    kind: &'a str,
+  ) -> BoxFuture<'a, Result<String>>;
+
+  fn fetch<'a>(&'a self, url: &'a str, out_path: &'a Path) -> BoxFuture<'a, Result<()>>;
+
+  /// 查询谱面元数据，供下载前过滤使用；不支持或查不到时返回 None。
+  fn chart_metadata<'a>(&'a self, _id: &'a str) -> BoxFuture<'a, Result<Option<ChartMeta>>> {
+    Box::pin(async { Ok(None) })
+  }
+}
+
+/// 下载站（api.milkbot.cn）及与其接口兼容的镜像。
+pub struct MilkbotSource {
+  client: Client,
+  base: String,
+  metadata_url: Option<String>,
+}
+
+impl MilkbotSource {
+  pub fn new(client: Client, base: &str) -> Self {
+    Self {
+      client,
+      base: base.trim_end_matches('/').to_string(),
+      metadata_url: None,
+    }
+  }
+
+  /// 元数据接口：`GET {url}?id=<id>` 返回 ChartMeta JSON，404 表示未收录。
+  pub fn with_metadata_url(mut self, url: Option<&str>) -> Self {
+    self.metadata_url = url
+      .map(|s| s.trim().to_string())
+      .filter(|s| !s.is_empty());
+    self
+  }
+}
+
+impl ChartSource for MilkbotSource {
+  fn endpoint(&self) -> &str {
+    &self.base
+  }
+
+  fn verify<'a>(&'a self, connect_sid: &'a str, code: &'a str) -> BoxFuture<'a, Result<String>> {
+    Box::pin(verify_key(&self.client, &self.base, connect_sid, code))
+  }
+
+  fn resolve_link<'a>(
+    &'a self,
+    connect_sid: &'a str,
+    key: &'a str,
+    id: &'a str,
+    kind: &'a str,
+  ) -> BoxFuture<'a, Result<String>> {
+    Box::pin(get_download_link(&self.client, &self.base, connect_sid, key, id, kind))
+  }
+
+  fn fetch<'a>(&'a self, url: &'a str, out_path: &'a Path) -> BoxFuture<'a, Result<()>> {
+    Box::pin(download_file(&self.client, url, out_path))
+  }
+
+  fn chart_metadata<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<ChartMeta>>> {
+    Box::pin(async move {
+      match &self.metadata_url {
+        Some(url) => get_chart_metadata(&self.client, url, id).await,
+        None => Ok(None),
+      }
+    })
+  }
+}
+
+/// API 地址优先级：任务参数 > 环境变量 NICONICO_API_BASE > 默认下载站。
+pub fn resolve_api_base(task_override: Option<&str>) -> String {
+  task_override
+    .map(|s| s.trim().to_string())
+    .filter(|s| !s.is_empty())
+    .or_else(|| {
+      std::env::var("NICONICO_API_BASE")
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+    })
+    .unwrap_or_else(|| DEFAULT_API_BASE.to_string())
+}
+
+#[derive(Debug, Deserialize)]
+struct VerifyResp {
+  success: bool,
+  key: Option<String>,
+  message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LinkResp {
+  success: bool,
+  url: Option<String>,
+  message: Option<String>,
+}
+
+async fn verify_key(client: &Client, base: &str, connect_sid: &str, code: &str) -> Result<String> {
+  let resp = client
+    .post(format!("{}/verify_captcha", base))
+    .header("Content-Type", "application/json")
+    .header("Cookie", format!("connect.sid={}", connect_sid))
+    .json(&serde_json::json!({ "code": code }))
+    .send()
+    .await
+    .context("verify_captcha request failed")?;
+
+  let status = resp.status();
+  let body = resp.text().await.context("verify_captcha read body failed")?;
+  let data: VerifyResp = serde_json::from_str(&body).with_context(|| {
+    format!(
+      "verify_captcha parse failed (status={}, body={})",
+      status,
+      truncate_for_log(&body, 300)
+    )
+  })?;
+
+  if !status.is_success() {
+    return Err(anyhow!(
+      "verify_captcha http {}: {}",
+      status,
+      data
+        .message
+        .unwrap_or_else(|| truncate_for_log(&body, 200))
+    ));
+  }
+
+  if data.success {
+    data.key.ok_or_else(|| anyhow!("verify_captcha success but key is empty"))
+  } else {
+    Err(anyhow!(
+      "verify_captcha failed: {}",
+      data.message.unwrap_or_else(|| "unknown".to_string())
+    ))
+  }
+}
+
+async fn get_download_link(
+  client: &Client,
+  base: &str,
+  connect_sid: &str,
+  key: &str,
+  id: &str,
+  kind: &str,
+) -> Result<String> {
+  let resp = client
+    .get(format!("{}/get_download_link", base))
+    .header("Cookie", format!("connect.sid={}", connect_sid))
+    .query(&[("id", id), ("key", key), ("type", kind)])
+    .send()
+    .await
+    .context("get_download_link request failed")?;
+
+  let status = resp.status();
+  let body = resp.text().await.context("get_download_link read body failed")?;
+  let data: LinkResp = serde_json::from_str(&body).with_context(|| {
+    format!(
+      "get_download_link parse failed (status={}, body={})",
+      status,
+      truncate_for_log(&body, 300)
+    )
+  })?;
+
+  if !status.is_success() {
+    return Err(anyhow!(
+      "get_download_link http {} for {}: {}",
+      status,
+      id,
+      data
+        .message
+        .unwrap_or_else(|| truncate_for_log(&body, 200))
+    ));
+  }
+
+  if data.success {
+    data.url.ok_or_else(|| anyhow!("get_download_link success but url is empty"))
+  } else {
+    Err(anyhow!(
+      "get_download_link failed for {}: {}",
+      id,
+      data.message.unwrap_or_else(|| "unknown".to_string())
+    ))
+  }
+}
+
+async fn get_chart_metadata(client: &Client, url: &str, id: &str) -> Result<Option<ChartMeta>> {
+  let resp = client
+    .get(url)
+    .query(&[("id", id)])
+    .send()
+    .await
+    .context("chart_metadata request failed")?;
+
+  let status = resp.status();
+  if status == StatusCode::NOT_FOUND {
+    return Ok(None);
+  }
+  let body = resp.text().await.context("chart_metadata read body failed")?;
+  if !status.is_success() {
+    return Err(anyhow!(
+      "chart_metadata http {} for {}: {}",
+      status,
+      id,
+      truncate_for_log(&body, 200)
+    ));
+  }
+  let meta: ChartMeta = serde_json::from_str(&body).with_context(|| {
+    format!(
+      "chart_metadata parse failed for {} (body={})",
+      id,
+      truncate_for_log(&body, 300)
+    )
+  })?;
+  Ok(Some(meta))
+}
+
+/// 续传所需的校验信息，与 `.part` 文件并排保存为 `.part.meta`。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PartMeta {
+  etag: Option<String>,
+  total_len: Option<u64>,
+}
+
+fn part_path_for(out_path: &Path) -> PathBuf {
+  out_path.with_extension(format!(
+    "{}.part",
+    out_path.extension().and_then(|e| e.to_str()).unwrap_or("tmp")
+  ))
+}
+
+fn part_meta_path_for(part_path: &Path) -> PathBuf {
+  let mut s = part_path.as_os_str().to_os_string();
+  s.push(".meta");
+  PathBuf::from(s)
+}
+
+async fn read_part_meta(path: &Path) -> Option<PartMeta> {
+  let bytes = fs::read(path).await.ok()?;
+  serde_json::from_slice(&bytes).ok()
+}
+
+async fn discard_part(part_path: &Path, meta_path: &Path) {
+  let _ = fs::remove_file(part_path).await;
+  let _ = fs::remove_file(meta_path).await;
+}
+
+fn header_str(resp: &reqwest::Response, name: header::HeaderName) -> Option<String> {
+  resp
+    .headers()
+    .get(name)
+    .and_then(|v| v.to_str().ok())
+    .map(|s| s.to_string())
+}
+
+/// 解析 `Content-Range: bytes <start>-<end>/<total>`，返回 (start, total)。
+fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+  let rest = value.trim().strip_prefix("bytes ")?;
+  let (range, total) = rest.split_once('/')?;
+  let (start, _) = range.split_once('-')?;
+  let start = start.trim().parse().ok()?;
+  let total = total.trim().parse().ok();
+  Some((start, total))
+}
+
+async fn download_file(client: &Client, url: &str, out_path: &Path) -> Result<()> {
+  if let Some(parent) = out_path.parent() {
+    fs::create_dir_all(parent).await?;
+  }
+
+  let tmp = part_path_for(out_path);
+  let meta_path = part_meta_path_for(&tmp);
+
+  let existing_len = fs::metadata(&tmp).await.map(|m| m.len()).unwrap_or(0);
+  // 没有校验信息的 .part 无法确认与服务端是同一文件，只能从头下载。
+  let saved_meta = if existing_len > 0 {
+    read_part_meta(&meta_path)
+      .await
+      .filter(|m| m.etag.is_some() || m.total_len.is_some())
+  } else {
+    None
+  };
+
+  let mut req = client.get(url);
+  if let Some(meta) = &saved_meta {
+    req = req.header(header::RANGE, format!("bytes={}-", existing_len));
+    if let Some(etag) = &meta.etag {
+      req = req.header(header::IF_RANGE, etag.as_str());
+    }
+  }
+
+  let resp = req
+    .send()
+    .await
+    .with_context(|| format!("download request failed: {}", url))?;
+
+  let status = resp.status();
+  if status == StatusCode::RANGE_NOT_SATISFIABLE {
+    if let Some(meta) = &saved_meta {
+      if meta.total_len == Some(existing_len) {
+        return finish_part(&tmp, &meta_path, out_path).await;
+      }
+    }
+    discard_part(&tmp, &meta_path).await;
+    return Err(anyhow!("download range not satisfiable, partial file discarded"));
+  }
+
+  if !status.is_success() {
+    return Err(anyhow!("download response status: {}", status));
+  }
+
+  let resp_etag = header_str(&resp, header::ETAG);
+  let (mut file, mut written, total_len) = if status == StatusCode::PARTIAL_CONTENT && saved_meta.is_some() {
+    let meta = saved_meta.clone().unwrap_or_default();
+    let range = header_str(&resp, header::CONTENT_RANGE).and_then(|v| parse_content_range(&v));
+    let (start, total) = match range {
+      Some(v) => v,
+      None => {
+        discard_part(&tmp, &meta_path).await;
+        return Err(anyhow!("download resume failed: missing or invalid Content-Range"));
+      }
+    };
+
+    let etag_changed = matches!((&meta.etag, &resp_etag), (Some(a), Some(b)) if a != b);
+    let len_changed = matches!((meta.total_len, total), (Some(a), Some(b)) if a != b);
+    if start != existing_len || etag_changed || len_changed {
+      discard_part(&tmp, &meta_path).await;
+      return Err(anyhow!(
+        "download resume mismatch (offset {} vs {}), partial file discarded",
+        start,
+        existing_len
+      ));
+    }
+
+    let file = fs::OpenOptions::new()
+      .append(true)
+      .open(&tmp)
+      .await
+      .with_context(|| format!("open partial file failed: {}", tmp.display()))?;
+    (file, existing_len, total.or(meta.total_len))
+  } else {
+    let meta = PartMeta {
+      etag: resp_etag,
+      total_len: resp.content_length(),
+    };
+    fs::write(&meta_path, serde_json::to_vec(&meta)?).await?;
+    let file = fs::File::create(&tmp).await?;
+    (file, 0, meta.total_len)
+  };
+
+  let mut stream = resp.bytes_stream();
+  while let Some(chunk) = stream.next().await {
+    let bytes = chunk?;
+    file.write_all(&bytes).await?;
+    written += bytes.len() as u64;
+  }
+  file.flush().await?;
+  drop(file);
+
+  if let Some(total) = total_len {
+    if written < total {
+      return Err(anyhow!("download incomplete: {} of {} bytes", written, total));
+    }
+    if written > total {
+      discard_part(&tmp, &meta_path).await;
+      return Err(VerifyError(format!("size mismatch: got {} bytes, Content-Length {}", written, total)).into());
+    }
+  }
+
+  finish_part(&tmp, &meta_path, out_path).await
+}
+
+/// 校验下载完整的 `.part` 并改名为目标文件。
+/// 校验不通过的文件无法续传修复，删除后由下一次重试从头下载。
+async fn finish_part(tmp: &Path, meta_path: &Path, out_path: &Path) -> Result<()> {
+  let check_path = tmp.to_path_buf();
+  let verified = tokio::task::spawn_blocking(move || archive::verify_chart_archive(&check_path, true))
+    .await
+    .context("verify task panicked")?;
+  if let Err(e) = verified {
+    discard_part(tmp, meta_path).await;
+    return Err(e.into());
+  }
+
+  fs::rename(tmp, out_path).await?;
+  let _ = fs::remove_file(meta_path).await;
+
+  Ok(())
+}
+
@@ -0,0 +1,75 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::fsutil;
+use crate::models::{DownloadTaskInput, TaskRecord};
+
+/// 任务历史：每个任务一个 `<task_id>.json`，保存在应用数据目录下。
+pub struct TaskStore {
+  dir: PathBuf,
+}
+
+impl TaskStore {
+  pub fn open(dir: PathBuf) -> Result<Self> {
+    fs::create_dir_all(&dir).with_context(|| format!("create task store dir failed: {}", dir.display()))?;
+    Ok(Self { dir })
+  }
+
+  fn record_path(&self, task_id: &str) -> Result<PathBuf> {
+    // task_id 来自前端参数，只接受 uuid 形式，避免拼出目录之外的路径。
+    if task_id.is_empty() || !task_id.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+      return Err(anyhow!("invalid task id: {}", task_id));
+    }
+    Ok(self.dir.join(format!("{}.json", task_id)))
+  }
+
+  pub fn load_all(&self) -> Vec<TaskRecord> {
+    let mut out = Vec::new();
+    let entries = match fs::read_dir(&self.dir) {
+      Ok(v) => v,
+      Err(_) => return out,
+    };
+    for entry in entries.flatten() {
+      let path = entry.path();
+      if path.extension().and_then(|e| e.to_str()) != Some("json") {
+        continue;
+      }
+      // 单个记录损坏不影响其余历史。
+      if let Ok(record) = read_record(&path) {
+        out.push(record);
+      }
+    }
+    out
+  }
+
+  pub fn save(&self, record: &TaskRecord) -> Result<()> {
+    let path = self.record_path(&record.state.task_id)?;
+    let bytes = serde_json::to_vec_pretty(record)?;
+    fsutil::write_atomic(&path, &bytes)
+  }
+
+  pub fn delete(&self, task_id: &str) -> Result<()> {
+    let path = self.record_path(task_id)?;
+    match fs::remove_file(&path) {
+      Ok(_) => Ok(()),
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+      Err(e) => Err(e).with_context(|| format!("delete task record failed: {}", path.display())),
+    }
+  }
+}
+
+fn read_record(path: &Path) -> Result<TaskRecord> {
+  let bytes = fs::read(path)?;
+  Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// 落盘前去掉 connect.sid / key / 验证码等凭据。
+pub fn redact_input(input: &DownloadTaskInput) -> DownloadTaskInput {
+  let mut out = input.clone();
+  out.connect_sid = String::new();
+  out.key = None;
+  out.captcha = None;
+  out
+}
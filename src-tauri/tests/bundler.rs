@@ -0,0 +1,481 @@
+mod common;
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use common::{chart_archive_with, RecordingSink};
+use niconico_app_lib::bundle_index::verify_bundle;
+use niconico_app_lib::bundle_task::run_bundle_task;
+use niconico_app_lib::bundler::{append_to_bundle, build_bundle_from_files, BundleOptions, ConflictPolicy};
+use niconico_app_lib::models::{BundleTaskInput, TaskState};
+use niconico_app_lib::{InnerState, TaskControl};
+use zip::ZipArchive;
+
+fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> Vec<u8> {
+  let mut buf = Vec::new();
+  archive.by_name(name).unwrap().read_to_end(&mut buf).unwrap();
+  buf
+}
+
+#[test]
+fn copies_entries_without_temp_dir() {
+  let dir = tempfile::tempdir().unwrap();
+  let bga = vec![7u8; 64 * 1024];
+  let a = dir.path().join("1201.adx");
+  let b = dir.path().join("1202.zip");
+  fs::write(&a, chart_archive_with("1201", &[("maidata.txt", b"&title=a\n"), ("pv.mp4", &bga)])).unwrap();
+  fs::write(&b, chart_archive_with("Song B", &[("maidata.txt", b"&title=b\n")])).unwrap();
+  let missing = dir.path().join("1203.adx");
+  let output = dir.path().join("out").join("bundle.adx");
+
+  let summary = build_bundle_from_files(&[a, b, missing], &output, &BundleOptions::default()).unwrap();
+
+  assert_eq!(summary.source_file_count, 3);
+  assert_eq!(summary.processed_count, 2);
+  assert!(summary.conflicts.is_empty());
+  let mut archive = ZipArchive::new(File::open(&output).unwrap()).unwrap();
+  let mut names: Vec<_> = archive.file_names().map(str::to_string).collect();
+  names.sort();
+  assert_eq!(
+    names,
+    ["1201/1201/maidata.txt", "1201/1201/pv.mp4", "1202/Song B/maidata.txt", "bundle_index.json"]
+  );
+  assert_eq!(read_entry(&mut archive, "1201/1201/pv.mp4"), bga);
+  assert_eq!(read_entry(&mut archive, "1202/Song B/maidata.txt"), b"&title=b\n");
+  let leftovers: Vec<_> = fs::read_dir(output.parent().unwrap()).unwrap().flatten().collect();
+  assert_eq!(leftovers.len(), 1);
+}
+
+#[test]
+fn resolves_conflicting_entries_by_policy() {
+  let dir = tempfile::tempdir().unwrap();
+  let old = dir.path().join("old").join("1301.adx");
+  let new = dir.path().join("new").join("1301.zip");
+  fs::create_dir_all(old.parent().unwrap()).unwrap();
+  fs::create_dir_all(new.parent().unwrap()).unwrap();
+  fs::write(&old, chart_archive_with("1301", &[("maidata.txt", b"old")])).unwrap();
+  std::thread::sleep(std::time::Duration::from_millis(20));
+  fs::write(&new, chart_archive_with("1301", &[("maidata.txt", b"new")])).unwrap();
+
+  let cases = [
+    (ConflictPolicy::Skip, "skipped", vec![("1301/1301/maidata.txt", "old")]),
+    (
+      ConflictPolicy::Rename,
+      "renamed",
+      vec![("1301/1301/maidata.txt", "old"), ("1301_2/1301/maidata.txt", "new")],
+    ),
+    (ConflictPolicy::KeepNewest, "replaced", vec![("1301/1301/maidata.txt", "new")]),
+  ];
+  for (policy, action, expected) in cases {
+    let output = dir.path().join(format!("{:?}.adx", policy));
+    let options = BundleOptions {
+      conflict_policy: policy,
+      ..BundleOptions::default()
+    };
+
+    let summary = build_bundle_from_files(&[old.clone(), new.clone()], &output, &options).unwrap();
+
+    assert_eq!(summary.conflicts.len(), 1, "{:?}", policy);
+    let conflict = &summary.conflicts[0];
+    assert_eq!(conflict.action, action);
+    assert_eq!(conflict.path, "1301/1301/maidata.txt");
+    assert_eq!((conflict.existing_source.as_str(), conflict.incoming_source.as_str()), ("1301.adx", "1301.zip"));
+    let mut archive = ZipArchive::new(File::open(&output).unwrap()).unwrap();
+    assert_eq!(archive.len(), expected.len() + 1, "{:?}", policy);
+    for (name, body) in expected {
+      assert_eq!(read_entry(&mut archive, name), body.as_bytes());
+    }
+  }
+}
+
+#[test]
+fn splits_into_numbered_parts() {
+  let dir = tempfile::tempdir().unwrap();
+  let sources: Vec<_> = ["1401", "1402", "1403"]
+    .iter()
+    .map(|id| {
+      let path = dir.path().join(format!("{}.adx", id));
+      fs::write(&path, chart_archive_with(id, &[("maidata.txt", id.as_bytes())])).unwrap();
+      path
+    })
+    .collect();
+  let output = dir.path().join("bundle_merged.adx");
+  let options = BundleOptions {
+    max_charts: Some(2),
+    ..BundleOptions::default()
+  };
+
+  let summary = build_bundle_from_files(&sources, &output, &options).unwrap();
+
+  let expected = [dir.path().join("bundle_merged_part01.adx"), dir.path().join("bundle_merged_part02.adx")];
+  assert_eq!(
+    summary.output_paths,
+    expected.iter().map(|p| p.to_string_lossy().to_string()).collect::<Vec<_>>()
+  );
+  assert!(!output.exists());
+  let counts: Vec<_> = expected
+    .iter()
+    .map(|p| ZipArchive::new(File::open(p).unwrap()).unwrap().len())
+    .collect();
+  assert_eq!(counts, [3, 2]);
+
+  let by_size = dir.path().join("by_size.adx");
+  let options = BundleOptions {
+    max_bytes: Some(1),
+    ..BundleOptions::default()
+  };
+  let summary = build_bundle_from_files(&sources, &by_size, &options).unwrap();
+  assert_eq!(summary.output_paths.len(), 3);
+}
+
+async fn run_bundle(input: BundleTaskInput, control: TaskControl) -> (TaskState, Arc<RecordingSink>) {
+  let state = Arc::new(InnerState::default());
+  let task_id = "bundle-task".to_string();
+  state.tasks.lock().insert(task_id.clone(), TaskState::new(task_id.clone()));
+  let sink = Arc::new(RecordingSink::default());
+
+  run_bundle_task(sink.clone(), state.clone(), task_id.clone(), input, Arc::new(control)).await;
+
+  let task = state.tasks.lock().get(&task_id).cloned().expect("task state");
+  (task, sink)
+}
+
+fn write_charts(dir: &Path, ids: &[&str]) {
+  for id in ids {
+    fs::write(dir.join(format!("{}.adx", id)), chart_archive_with(id, &[("maidata.txt", id.as_bytes())])).unwrap();
+  }
+}
+
+#[tokio::test]
+async fn bundle_task_resolves_manifest_ids_in_dir() {
+  let dir = tempfile::tempdir().unwrap();
+  write_charts(dir.path(), &["1501", "1502", "1503"]);
+  let manifest = dir.path().join("manifest.json");
+  fs::write(&manifest, r#"{"name":"picks","levelIds":["1501","1503","1599"]}"#).unwrap();
+  let output = dir.path().join("picks.adx");
+  let input = BundleTaskInput {
+    source_dir: Some(dir.path().to_string_lossy().to_string()),
+    manifest_paths: Some(vec![manifest.to_string_lossy().to_string()]),
+    output_path: Some(output.to_string_lossy().to_string()),
+    ..BundleTaskInput::default()
+  };
+
+  let (task, sink) = run_bundle(input, TaskControl::default()).await;
+
+  assert_eq!(task.status, "completed");
+  assert_eq!((task.total_ids, task.processed_ids), (2, 2));
+  assert_eq!((task.bundled_count, task.ok_count), (2, 0));
+  assert_eq!(task.bundle_output_paths, vec![output.to_string_lossy().to_string()]);
+  assert!(task.logs.iter().any(|l| l.contains("1599")));
+  let mut names: Vec<_> = ZipArchive::new(File::open(&output).unwrap())
+    .unwrap()
+    .file_names()
+    .map(str::to_string)
+    .collect();
+  names.sort();
+  assert_eq!(names, ["1501/1501/maidata.txt", "1503/1503/maidata.txt", "bundle_index.json"]);
+  let progress = sink.events.lock().iter().filter(|e| e.event == "bundle_progress").count();
+  assert_eq!(progress, 2);
+}
+
+#[tokio::test]
+async fn bundle_task_skips_earlier_bundles_in_dir() {
+  let dir = tempfile::tempdir().unwrap();
+  write_charts(dir.path(), &["1551", "1552"]);
+  let input = BundleTaskInput {
+    source_dir: Some(dir.path().to_string_lossy().to_string()),
+    ..BundleTaskInput::default()
+  };
+  let (first, _) = run_bundle(input.clone(), TaskControl::default()).await;
+  assert_eq!(first.status, "completed");
+  // 时间戳精确到秒，等到下一秒以免与上一次的输出同名。
+  tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+  write_charts(dir.path(), &["1553"]);
+
+  let (task, _) = run_bundle(input, TaskControl::default()).await;
+
+  assert_eq!(task.status, "completed");
+  assert_eq!((task.total_ids, task.bundled_count), (3, 3));
+  assert_ne!(task.bundle_output_paths, first.bundle_output_paths);
+  let mut names: Vec<_> = ZipArchive::new(File::open(&task.bundle_output_paths[0]).unwrap())
+    .unwrap()
+    .file_names()
+    .map(str::to_string)
+    .collect();
+  names.sort();
+  assert_eq!(
+    names,
+    ["1551/1551/maidata.txt", "1552/1552/maidata.txt", "1553/1553/maidata.txt", "bundle_index.json"]
+  );
+}
+
+#[tokio::test]
+async fn bundle_progress_counts_skipped_files() {
+  let dir = tempfile::tempdir().unwrap();
+  write_charts(dir.path(), &["1571", "1572"]);
+  // 与 1571.adx 写出相同的条目路径，按 skip 策略被跳过。
+  fs::write(dir.path().join("1571.zip"), chart_archive_with("1571", &[("maidata.txt", b"dup")])).unwrap();
+  let input = BundleTaskInput {
+    source_dir: Some(dir.path().to_string_lossy().to_string()),
+    output_path: Some(dir.path().join("out").join("all.adx").to_string_lossy().to_string()),
+    conflict_policy: Some("skip".to_string()),
+    ..BundleTaskInput::default()
+  };
+
+  let (task, sink) = run_bundle(input, TaskControl::default()).await;
+
+  assert_eq!(task.status, "completed");
+  assert_eq!((task.total_ids, task.processed_ids, task.bundled_count), (3, 3, 2));
+  let progress: Vec<_> = sink
+    .events
+    .lock()
+    .iter()
+    .filter(|e| e.event == "bundle_progress")
+    .map(|e| e.message.clone())
+    .collect();
+  assert_eq!(progress, ["[2/3] 1571.adx", "[3/3] 1572.adx"]);
+}
+
+#[tokio::test]
+async fn paused_bundle_task_waits_between_charts() {
+  let dir = tempfile::tempdir().unwrap();
+  write_charts(dir.path(), &["1651", "1652"]);
+  let output = dir.path().join("paused.adx");
+  let input = BundleTaskInput {
+    source_dir: Some(dir.path().to_string_lossy().to_string()),
+    output_path: Some(output.to_string_lossy().to_string()),
+    ..BundleTaskInput::default()
+  };
+  let state = Arc::new(InnerState::default());
+  let task_id = "paused-bundle".to_string();
+  state.tasks.lock().insert(task_id.clone(), TaskState::new(task_id.clone()));
+  let control = Arc::new(TaskControl::default());
+  control.pause();
+
+  let run = tokio::spawn(run_bundle_task(
+    Arc::new(RecordingSink::default()),
+    state.clone(),
+    task_id.clone(),
+    input,
+    control.clone(),
+  ));
+  tokio::time::sleep(std::time::Duration::from_millis(400)).await;
+  assert_eq!(state.tasks.lock()[&task_id].bundled_count, 0);
+  assert!(!output.exists());
+
+  control.resume();
+  run.await.unwrap();
+  let task = state.tasks.lock()[&task_id].clone();
+  assert_eq!(task.status, "completed");
+  assert_eq!(task.bundled_count, 2);
+  assert!(output.exists());
+}
+
+#[tokio::test]
+async fn bundle_task_can_be_cancelled() {
+  let dir = tempfile::tempdir().unwrap();
+  write_charts(dir.path(), &["1601", "1602"]);
+  let output = dir.path().join("out").join("all.adx");
+  let input = BundleTaskInput {
+    source_dir: Some(dir.path().to_string_lossy().to_string()),
+    output_path: Some(output.to_string_lossy().to_string()),
+    ..BundleTaskInput::default()
+  };
+  let control = TaskControl::default();
+  control.cancel();
+
+  let (task, _) = run_bundle(input, control).await;
+
+  assert_eq!(task.status, "cancelled");
+  assert!(!output.exists());
+  assert_eq!(fs::read_dir(output.parent().unwrap()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn per_manifest_bundles_embed_filtered_collection() {
+  let dir = tempfile::tempdir().unwrap();
+  write_charts(dir.path(), &["1701", "1702", "1703"]);
+  let lists = dir.path().join("collections");
+  for (folder, ids) in [("Picks A", r#"["1701","1702","1799"]"#), ("Picks B", r#"["1703"]"#), ("Empty", r#"["1798"]"#)] {
+    fs::create_dir_all(lists.join(folder)).unwrap();
+    fs::write(
+      lists.join(folder).join("manifest.json"),
+      format!(r#"{{"name":"{} list","levelIds":{}}}"#, folder, ids),
+    )
+    .unwrap();
+  }
+  let out = dir.path().join("bundles");
+  let manifests = ["Picks A", "Picks B", "Empty"]
+    .iter()
+    .map(|f| lists.join(f).join("manifest.json").to_string_lossy().to_string())
+    .collect();
+  let input = BundleTaskInput {
+    source_dir: Some(dir.path().to_string_lossy().to_string()),
+    manifest_paths: Some(manifests),
+    output_path: Some(out.to_string_lossy().to_string()),
+    per_manifest: true,
+    ..BundleTaskInput::default()
+  };
+
+  let (task, _) = run_bundle(input, TaskControl::default()).await;
+
+  assert_eq!(task.status, "completed");
+  assert_eq!((task.total_ids, task.processed_ids), (3, 3));
+  assert_eq!(task.bundle_output_paths.len(), 2);
+  assert!(!out.join("Empty.adx").exists());
+  let mut archive = ZipArchive::new(File::open(out.join("Picks A.adx")).unwrap()).unwrap();
+  let manifest: serde_json::Value =
+    serde_json::from_slice(&read_entry(&mut archive, "collections/Picks A/manifest.json")).unwrap();
+  assert_eq!(manifest, serde_json::json!({ "name": "Picks A list", "levelIds": ["1701", "1702"] }));
+  assert!(archive.by_name("1703/1703/maidata.txt").is_err());
+}
+
+#[tokio::test]
+async fn per_manifest_bundles_with_clashing_names_are_suffixed() {
+  let dir = tempfile::tempdir().unwrap();
+  write_charts(dir.path(), &["1751", "1752"]);
+  let mut manifests = Vec::new();
+  for (root, folder, id) in [("official", "Picks", "1751"), ("custom", "picks", "1752")] {
+    let path = dir.path().join(root).join(folder).join("manifest.json");
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, format!(r#"{{"name":"{}","levelIds":["{}"]}}"#, root, id)).unwrap();
+    manifests.push(path.to_string_lossy().to_string());
+  }
+  let out = dir.path().join("bundles");
+  let input = BundleTaskInput {
+    source_dir: Some(dir.path().to_string_lossy().to_string()),
+    manifest_paths: Some(manifests),
+    output_path: Some(out.to_string_lossy().to_string()),
+    per_manifest: true,
+    ..BundleTaskInput::default()
+  };
+
+  let (task, _) = run_bundle(input, TaskControl::default()).await;
+
+  assert_eq!(task.status, "completed");
+  let names: Vec<_> = task
+    .bundle_output_paths
+    .iter()
+    .map(|p| Path::new(p).file_name().unwrap().to_string_lossy().to_string())
+    .collect();
+  assert_eq!(names, ["Picks.adx", "picks (2).adx"]);
+  assert!(task.logs.iter().any(|l| l.contains("picks (2).adx")));
+  let mut second = ZipArchive::new(File::open(out.join("picks (2).adx")).unwrap()).unwrap();
+  assert!(second.by_name("1752/1752/maidata.txt").is_ok());
+}
+
+#[test]
+fn appends_only_missing_charts() {
+  let dir = tempfile::tempdir().unwrap();
+  write_charts(dir.path(), &["1801", "1802", "1803"]);
+  let chart = |id: &str| dir.path().join(format!("{}.adx", id));
+  let bundle = dir.path().join("everything.adx");
+  build_bundle_from_files(&[chart("1801"), chart("1802")], &bundle, &BundleOptions::default()).unwrap();
+
+  let summary =
+    append_to_bundle(&bundle, &[chart("1802"), chart("1803")], &BundleOptions::default(), &()).unwrap();
+
+  assert_eq!(summary.added, ["1803.adx"]);
+  assert_eq!(summary.already_present, ["1802.adx"]);
+  assert!(summary.conflicts.is_empty());
+  let mut archive = ZipArchive::new(File::open(&bundle).unwrap()).unwrap();
+  let mut names: Vec<_> = archive.file_names().map(str::to_string).collect();
+  names.sort();
+  assert_eq!(
+    names,
+    ["1801/1801/maidata.txt", "1802/1802/maidata.txt", "1803/1803/maidata.txt", "bundle_index.json"]
+  );
+  assert_eq!(read_entry(&mut archive, "1801/1801/maidata.txt"), b"1801");
+  assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 4);
+  let report = verify_bundle(&bundle).unwrap();
+  assert_eq!((report.chart_count, report.ok_count), (3, 3));
+}
+
+#[tokio::test]
+async fn appends_to_bundle_inside_source_dir() {
+  let dir = tempfile::tempdir().unwrap();
+  write_charts(dir.path(), &["1851", "1852"]);
+  let chart = |id: &str| dir.path().join(format!("{}.adx", id));
+  let bundle = dir.path().join("all.adx");
+  build_bundle_from_files(&[chart("1851"), chart("1852")], &bundle, &BundleOptions::default()).unwrap();
+  write_charts(dir.path(), &["1853"]);
+
+  // 直接把整合包自身列为来源也不会被追加进去。
+  let summary = append_to_bundle(&bundle, &[bundle.clone(), chart("1851")], &BundleOptions::default(), &()).unwrap();
+  assert!(summary.added.is_empty());
+  assert_eq!(summary.already_present, ["1851.adx"]);
+
+  let input = BundleTaskInput {
+    source_dir: Some(dir.path().to_string_lossy().to_string()),
+    output_path: Some(bundle.to_string_lossy().to_string()),
+    append: true,
+    ..BundleTaskInput::default()
+  };
+  let (task, _) = run_bundle(input, TaskControl::default()).await;
+
+  assert_eq!(task.status, "completed");
+  assert!(task.logs.iter().any(|l| l.contains("写入 1，已存在 2")));
+  assert_eq!((task.total_ids, task.processed_ids, task.bundled_count), (3, 3, 1));
+  let mut names: Vec<_> = ZipArchive::new(File::open(&bundle).unwrap())
+    .unwrap()
+    .file_names()
+    .map(str::to_string)
+    .collect();
+  names.sort();
+  assert_eq!(
+    names,
+    ["1851/1851/maidata.txt", "1852/1852/maidata.txt", "1853/1853/maidata.txt", "bundle_index.json"]
+  );
+  let report = verify_bundle(&bundle).unwrap();
+  assert_eq!((report.chart_count, report.ok_count), (3, 3));
+}
+
+#[test]
+fn verify_reports_missing_and_corrupt_charts() {
+  let dir = tempfile::tempdir().unwrap();
+  write_charts(dir.path(), &["1901", "1902", "1903"]);
+  let sources: Vec<_> = ["1901", "1902", "1903"]
+    .iter()
+    .map(|id| dir.path().join(format!("{}.adx", id)))
+    .collect();
+  let bundle = dir.path().join("bundle.adx");
+  build_bundle_from_files(&sources, &bundle, &BundleOptions::default()).unwrap();
+
+  let report = verify_bundle(&bundle).unwrap();
+  assert_eq!((report.chart_count, report.ok_count), (3, 3));
+  assert!(report.problems.is_empty() && report.unindexed_entries.is_empty());
+
+  let tampered = dir.path().join("tampered.adx");
+  {
+    let mut archive = ZipArchive::new(File::open(&bundle).unwrap()).unwrap();
+    let mut writer = zip::ZipWriter::new(File::create(&tampered).unwrap());
+    for index in 0..archive.len() {
+      let entry = archive.by_index_raw(index).unwrap();
+      match entry.name() {
+        "1901/1901/maidata.txt" => {}
+        "1902/1902/maidata.txt" => {
+          drop(entry);
+          writer.start_file("1902/1902/maidata.txt", Default::default()).unwrap();
+          writer.write_all(b"9999").unwrap();
+        }
+        _ => writer.raw_copy_file(entry).unwrap(),
+      }
+    }
+    writer.start_file("extra/readme.txt", Default::default()).unwrap();
+    writer.finish().unwrap();
+  }
+
+  let report = verify_bundle(&tampered).unwrap();
+
+  assert_eq!(
+    (report.chart_count, report.ok_count, report.missing_count, report.corrupt_count),
+    (3, 1, 1, 1)
+  );
+  let statuses: Vec<_> = report.problems.iter().map(|c| (c.level_id.as_str(), c.status.as_str())).collect();
+  assert_eq!(statuses, [("1901", "missing"), ("1902", "corrupt")]);
+  assert!(report.problems[1].problems[0].contains("sha256"));
+  assert_eq!(report.unindexed_entries, ["extra/readme.txt"]);
+  assert!(verify_bundle(&sources[0]).is_err());
+}
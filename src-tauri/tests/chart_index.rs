@@ -0,0 +1,86 @@
+mod common;
+
+use std::fs;
+
+use common::chart_archive_with;
+use niconico_app_lib::chart_index::{build_index, load_index, query_index, ChartQuery};
+use niconico_app_lib::maidata::{level_value, parse_maidata};
+
+const SAMPLE: &str = "\u{feff}&title=Oshama Scramble!
+&artist=t+pazolite
+&wholebpm=190
+&first=1.25
+&des=default charter
+&lv_4=11
+&lv_5=13+
+&des_5=Luxury
+&inote_4=(190){4}1,2,
+3,4,
+E
+&lv_6=14
+&des_6=mai-Star
+&inote_6=(190){8}1,2,3,4,E
+&lv_7=15?
+&inote_7=
+";
+
+#[test]
+fn parses_maidata_fields() {
+  let meta = parse_maidata(SAMPLE);
+
+  assert_eq!(meta.title.as_deref(), Some("Oshama Scramble!"));
+  assert_eq!(meta.artist.as_deref(), Some("t+pazolite"));
+  assert_eq!(meta.designer.as_deref(), Some("default charter"));
+  assert_eq!(meta.wholebpm.as_deref(), Some("190"));
+  assert_eq!(meta.first, Some(1.25));
+
+  let names: Vec<_> = meta.difficulties.iter().map(|d| d.name.as_str()).collect();
+  assert_eq!(names, vec!["expert", "master", "remaster", "utage"]);
+  assert!(meta.difficulty("expert").unwrap().has_chart);
+  assert!(!meta.difficulty("master").unwrap().has_chart);
+  assert_eq!(meta.difficulty("master").unwrap().level.as_deref(), Some("13+"));
+  assert_eq!(meta.difficulty("remaster").unwrap().designer.as_deref(), Some("mai-Star"));
+  assert!(!meta.difficulty("utage").unwrap().has_chart);
+  assert_eq!(meta.designers(), vec!["default charter", "Luxury", "mai-Star"]);
+}
+
+#[test]
+fn converts_levels() {
+  assert_eq!(level_value("13"), Some(13.0));
+  assert_eq!(level_value("13+"), Some(13.5));
+  assert_eq!(level_value("14.7"), Some(14.7));
+  assert_eq!(level_value("15?"), Some(15.0));
+  assert_eq!(level_value("?"), None);
+}
+
+#[test]
+fn builds_and_queries_index() {
+  let dir = tempfile::tempdir().unwrap();
+  let a = "&title=Alpha\n&artist=Foo\n&des=Bob\n&lv_5=13\n&inote_5=1,E\n";
+  let b = "&title=Beta\n&artist=Bar\n&lv_5=12+\n&inote_5=1,E\n&lv_6=14\n&des_6=Carol\n&inote_6=1,E\n";
+  fs::write(dir.path().join("1.adx"), chart_archive_with("Alpha", &[("maidata.txt", a.as_bytes())])).unwrap();
+  fs::write(dir.path().join("2.adx"), chart_archive_with("Beta", &[("maidata.txt", b.as_bytes())])).unwrap();
+  fs::write(dir.path().join("3.adx"), b"broken").unwrap();
+
+  let summary = build_index(dir.path(), &[]).unwrap();
+  assert_eq!(summary.total, 2);
+  assert_eq!(summary.parsed, 2);
+
+  let again = build_index(dir.path(), &[]).unwrap();
+  assert_eq!(again.reused, 2);
+  assert_eq!(again.parsed, 0);
+
+  let index = load_index(dir.path());
+  assert_eq!(index.entries["2"].folder, "Beta");
+
+  let ids = |q: ChartQuery| query_index(&index, &q).into_iter().map(|e| e.level_id).collect::<Vec<_>>();
+  assert_eq!(ids(ChartQuery::default()), vec!["1", "2"]);
+  assert_eq!(ids(ChartQuery { title: Some("alp".into()), ..Default::default() }), vec!["1"]);
+  assert_eq!(ids(ChartQuery { artist: Some("bar".into()), ..Default::default() }), vec!["2"]);
+  assert_eq!(ids(ChartQuery { charter: Some("carol".into()), ..Default::default() }), vec!["2"]);
+  assert_eq!(ids(ChartQuery { level_min: Some(13.5), ..Default::default() }), vec!["2"]);
+  assert_eq!(
+    ids(ChartQuery { difficulty: Some("master".into()), level_min: Some(13.0), ..Default::default() }),
+    vec!["1"]
+  );
+}
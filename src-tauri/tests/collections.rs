@@ -0,0 +1,299 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use niconico_app_lib::collections::{
+  check_manifest_file, combine_level_ids, combine_manifests, create_manifest, diff_collections, export_collections,
+  merge_collections, parse_manifest_file, update_manifest, validate_collections_dir, SetOp,
+};
+use niconico_app_lib::overlay_watch::{spawn_watcher, KnownManifests};
+
+fn write_manifest(root: &Path, folder: &str, name: &str, ids: &[&str]) {
+  fs::create_dir_all(root.join(folder)).unwrap();
+  let manifest = serde_json::json!({ "name": name, "levelIds": ids });
+  fs::write(root.join(folder).join("manifest.json"), manifest.to_string()).unwrap();
+}
+
+#[test]
+fn diffs_manifests_between_snapshots() {
+  let old = tempfile::tempdir().unwrap();
+  let new = tempfile::tempdir().unwrap();
+  write_manifest(old.path(), "same", "Same", &["1", "2"]);
+  write_manifest(new.path(), "same", "Same", &["2", "1"]);
+  write_manifest(old.path(), "grown", "Grown", &["10", "11"]);
+  write_manifest(new.path(), "grown", "Grown", &["11", "12", "13"]);
+  write_manifest(old.path(), "title", "Old Title", &["20"]);
+  write_manifest(new.path(), "title", "New Title", &["20"]);
+  write_manifest(old.path(), "before", "Moved", &["30", "31"]);
+  write_manifest(new.path(), "after", "Moved", &["31", "30"]);
+  write_manifest(old.path(), "dropped", "Dropped", &["40"]);
+  write_manifest(new.path(), "fresh", "Fresh", &["50", "12"]);
+
+  let diff = diff_collections(old.path(), new.path()).unwrap();
+
+  assert_eq!(diff.unchanged_count, 1);
+  let summary: Vec<_> = diff
+    .manifests
+    .iter()
+    .map(|m| (m.relative_path.as_str(), m.status.as_str()))
+    .collect();
+  assert_eq!(
+    summary,
+    [
+      ("after/manifest.json", "renamed"),
+      ("dropped/manifest.json", "removed"),
+      ("fresh/manifest.json", "added"),
+      ("grown/manifest.json", "changed"),
+      ("title/manifest.json", "renamed"),
+    ]
+  );
+  let by_path = |rel: &str| diff.manifests.iter().find(|m| m.relative_path == rel).unwrap();
+  assert_eq!(by_path("after/manifest.json").previous_relative_path.as_deref(), Some("before/manifest.json"));
+  assert_eq!(by_path("grown/manifest.json").added_ids, ["12", "13"]);
+  assert_eq!(by_path("grown/manifest.json").removed_ids, ["10"]);
+  assert_eq!(by_path("title/manifest.json").previous_name.as_deref(), Some("Old Title"));
+  assert_eq!(by_path("dropped/manifest.json").removed_ids, ["40"]);
+  assert_eq!(diff.added_ids, ["50", "12", "13"]);
+  assert_eq!(diff.removed_ids, ["40", "10"]);
+}
+
+#[test]
+fn diff_rejects_missing_roots_and_reports_malformed_manifests() {
+  let old = tempfile::tempdir().unwrap();
+  let new = tempfile::tempdir().unwrap();
+  write_manifest(old.path(), "ok", "Ok", &["1"]);
+  assert!(diff_collections(&old.path().join("nope"), old.path()).is_err());
+
+  write_manifest(old.path(), "bad", "Bad", &["2"]);
+  write_manifest(new.path(), "ok", "Ok", &["1", "3"]);
+  fs::create_dir_all(new.path().join("bad")).unwrap();
+  fs::write(new.path().join("bad").join("manifest.json"), "{").unwrap();
+
+  let diff = diff_collections(old.path(), new.path()).unwrap();
+
+  assert_eq!(diff.added_ids, ["3"]);
+  // 解析失败的清单不被当作删除。
+  assert!(diff.removed_ids.is_empty());
+  assert_eq!(diff.manifests.len(), 1);
+  assert_eq!(diff.manifests[0].relative_path, "ok/manifest.json");
+  assert_eq!(diff.errors.len(), 1);
+  assert!(diff.errors[0].path.contains("bad"));
+  assert_eq!(diff.errors[0].kind, "json");
+}
+
+fn ids(values: &[&str]) -> Vec<String> {
+  values.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn creates_and_updates_manifests_atomically() {
+  let root = tempfile::tempdir().unwrap();
+
+  let meta = create_manifest(root.path(), "Picks", "My Picks", &ids(&["1", "2", "2", " 3 "])).unwrap();
+
+  assert_eq!((meta.relative_path.as_str(), meta.level_count), ("Picks/manifest.json", 3));
+  let path = root.path().join("Picks").join("manifest.json");
+  let written: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+  assert_eq!(written, serde_json::json!({ "name": "My Picks", "levelIds": ["1", "2", "3"] }));
+  assert!(create_manifest(root.path(), "Picks", "Again", &ids(&["9"])).is_err());
+  assert!(create_manifest(root.path(), "../escape", "Bad", &ids(&["9"])).is_err());
+  assert!(create_manifest(root.path(), "Empty", "Empty", &[]).is_err());
+  assert!(!root.path().join("Empty").join("manifest.json").exists());
+
+  let meta = update_manifest(root.path(), &path, &path, &ids(&["4", "1"]), &ids(&["2"]), Some("Renamed")).unwrap();
+
+  assert_eq!((meta.name.as_str(), meta.level_count), ("Renamed", 3));
+  let parsed = parse_manifest_file(&path).unwrap();
+  assert_eq!(parsed.level_ids, ["1", "3", "4"]);
+  assert!(update_manifest(root.path(), &path, &path, &[], &ids(&["1", "3", "4"]), None).is_err());
+  assert_eq!(parse_manifest_file(&path).unwrap().level_ids, ["1", "3", "4"]);
+  assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+
+  let builtin = tempfile::tempdir().unwrap();
+  write_manifest(builtin.path(), "Official", "Official", &["7", "8"]);
+  let source = builtin.path().join("Official").join("manifest.json");
+  let target = root.path().join("Official").join("manifest.json");
+  update_manifest(root.path(), &source, &target, &ids(&["9"]), &[], None).unwrap();
+  assert_eq!(parse_manifest_file(&target).unwrap().level_ids, ["7", "8", "9"]);
+  assert_eq!(parse_manifest_file(&source).unwrap().level_ids, ["7", "8"]);
+}
+
+#[test]
+fn combines_manifests_with_set_operations() {
+  let lists = [ids(&["1", "2", "3"]), ids(&["3", "4", "2"]), ids(&["2", "5"])];
+  assert_eq!(combine_level_ids(SetOp::Union, &lists), ["1", "2", "3", "4", "5"]);
+  assert_eq!(combine_level_ids(SetOp::Intersection, &lists), ["2"]);
+  assert_eq!(combine_level_ids(SetOp::Difference, &lists), ["1"]);
+  assert_eq!(SetOp::parse("Intersect").unwrap(), SetOp::Intersection);
+  assert!(SetOp::parse("xor").is_err());
+
+  let root = tempfile::tempdir().unwrap();
+  write_manifest(root.path(), "a", "A", &["1", "2", "3"]);
+  write_manifest(root.path(), "b", "B", &["2", "3"]);
+  let sources = [root.path().join("a").join("manifest.json"), root.path().join("b").join("manifest.json")];
+
+  let meta = combine_manifests(root.path(), SetOp::Difference, &sources, "a-minus-b", "A - B").unwrap();
+
+  assert_eq!(meta.level_count, 1);
+  assert_eq!(parse_manifest_file(&root.path().join("a-minus-b").join("manifest.json")).unwrap().level_ids, ["1"]);
+  let reversed = [sources[1].clone(), sources[0].clone()];
+  assert!(combine_manifests(root.path(), SetOp::Difference, &reversed, "b-minus-a", "B - A").is_err());
+}
+
+#[test]
+fn exports_collections_trimmed_to_local_charts() {
+  let lists = tempfile::tempdir().unwrap();
+  write_manifest(lists.path(), "Picks", "Picks", &["1", "2", "3"]);
+  write_manifest(&lists.path().join("nested"), "Picks", "Other Picks", &["3"]);
+  write_manifest(lists.path(), "Missing", "Missing", &["9"]);
+  let sources = [
+    lists.path().join("Picks").join("manifest.json"),
+    lists.path().join("nested").join("Picks").join("manifest.json"),
+    lists.path().join("Missing").join("manifest.json"),
+  ];
+  let charts = tempfile::tempdir().unwrap();
+  fs::write(charts.path().join("1.adx"), b"x").unwrap();
+  fs::write(charts.path().join("3.zip"), b"x").unwrap();
+  let target = tempfile::tempdir().unwrap();
+
+  let report = export_collections(&sources, target.path(), Some(charts.path())).unwrap();
+
+  let folders: Vec<_> = report.exported.iter().map(|m| m.folder.as_str()).collect();
+  assert_eq!(folders, ["Picks", "Picks_2"]);
+  assert_eq!(report.exported[0].removed_ids, ["2"]);
+  assert_eq!(report.skipped.len(), 1);
+  let exported = target.path().join("collections").join("Picks").join("manifest.json");
+  let written: serde_json::Value = serde_json::from_slice(&fs::read(exported).unwrap()).unwrap();
+  assert_eq!(written, serde_json::json!({ "name": "Picks", "levelIds": ["1", "3"] }));
+  assert!(!target.path().join("collections").join("Missing").exists());
+
+  let untrimmed = export_collections(&sources[..1], target.path(), None).unwrap();
+  assert_eq!(untrimmed.exported[0].level_count, 3);
+}
+
+#[test]
+fn known_manifests_resolve_file_and_directory_events() {
+  let root = tempfile::tempdir().unwrap();
+  write_manifest(root.path(), "kept", "Kept", &["1"]);
+  write_manifest(root.path(), "edited", "Edited", &["2"]);
+  write_manifest(root.path(), "gone", "Gone", &["3"]);
+  let mut known = KnownManifests::scan(root.path());
+  assert!(known.apply(root.path(), &[root.path().join("unrelated.txt")]).is_none());
+
+  write_manifest(root.path(), "edited", "Edited", &["2", "20", "21"]);
+  fs::remove_dir_all(root.path().join("gone")).unwrap();
+  write_manifest(root.path(), "fresh", "Fresh", &["4"]);
+  fs::create_dir_all(root.path().join("broken")).unwrap();
+  fs::write(root.path().join("broken/manifest.json"), "{ \"name\": ").unwrap();
+  let events = [
+    root.path().join("edited/manifest.json"),
+    root.path().join("gone"),
+    root.path().join("fresh"),
+    root.path().join("broken/manifest.json"),
+  ];
+
+  let change = known.apply(root.path(), &events).unwrap();
+  let changed: Vec<_> = change
+    .changed
+    .iter()
+    .map(|m| (m.relative_path.as_str(), m.level_count, m.source.as_str()))
+    .collect();
+  assert_eq!(changed, [("edited/manifest.json", 3, "overlay"), ("fresh/manifest.json", 1, "overlay")]);
+  assert_eq!(change.removed.len(), 1);
+  assert!(change.removed[0].ends_with("manifest.json") && change.removed[0].contains("gone"));
+  assert_eq!(change.errors.len(), 1);
+  assert!(change.errors[0].path.contains("broken"));
+  assert_eq!(change.errors[0].kind, "json");
+}
+
+#[tokio::test]
+async fn watcher_emits_collections_changed_for_edits() {
+  let root = tempfile::tempdir().unwrap();
+  write_manifest(root.path(), "edited", "Edited", &["2"]);
+  let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+  let watcher = spawn_watcher(root.path().to_path_buf(), move |change| {
+    let _ = tx.send(change);
+  })
+  .unwrap();
+  tokio::time::sleep(Duration::from_millis(200)).await;
+
+  // 大小不变的改写也要被发现。
+  write_manifest(root.path(), "edited", "Edit 2", &["2"]);
+  fs::create_dir_all(root.path().join("broken")).unwrap();
+  fs::write(root.path().join("broken/manifest.json"), "{ \"levelIds\": [").unwrap();
+
+  let mut changed = Vec::new();
+  let mut errors = Vec::new();
+  let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+  while changed.is_empty() || errors.is_empty() {
+    let change = tokio::time::timeout_at(deadline, rx.recv())
+      .await
+      .expect("collections_changed not received")
+      .unwrap();
+    assert_eq!(change.root, root.path().to_string_lossy());
+    changed.extend(change.changed);
+    errors.extend(change.errors);
+  }
+  let last = changed.iter().rev().find(|m| m.relative_path == "edited/manifest.json").unwrap();
+  assert_eq!((last.name.as_str(), last.level_count), ("Edit 2", 1));
+  assert!(errors.iter().all(|e| e.path.contains("broken") && e.kind == "json"));
+
+  watcher.stop();
+  tokio::time::sleep(Duration::from_millis(100)).await;
+  while rx.try_recv().is_ok() {}
+  write_manifest(root.path(), "edited", "Edit 3", &["2"]);
+  tokio::time::sleep(Duration::from_millis(800)).await;
+  assert!(rx.try_recv().is_err());
+}
+
+#[test]
+fn listing_keeps_valid_manifests_and_reports_broken_ones() {
+  let builtin = tempfile::tempdir().unwrap();
+  let overlay = tempfile::tempdir().unwrap();
+  write_manifest(builtin.path(), "a", "A", &["1"]);
+  write_manifest(builtin.path(), "shared", "Shared", &["2"]);
+  write_manifest(overlay.path(), "shared", "Shared Overlay", &["2", "3"]);
+  let write_raw = |folder: &str, content: &str| {
+    fs::create_dir_all(overlay.path().join(folder)).unwrap();
+    fs::write(overlay.path().join(folder).join("manifest.json"), content).unwrap();
+  };
+  write_raw("blank", "  \n");
+  write_raw("syntax", "{\n  \"name\": \"X\",\n  \"levelIds\": [1,]\n}");
+  write_raw("no-ids", r#"{ "name": "No IDs" }"#);
+  write_raw("no-valid-ids", r#"{ "levelIds": [null, {}] }"#);
+
+  let listing = merge_collections(builtin.path(), Some(overlay.path()));
+  let names: Vec<_> = listing.manifests.iter().map(|m| (m.name.as_str(), m.source.as_str())).collect();
+  assert_eq!(names, [("A", "builtin"), ("Shared Overlay", "overlay")]);
+
+  let mut kinds: Vec<_> = listing
+    .errors
+    .iter()
+    .map(|e| (Path::new(&e.path).parent().unwrap().file_name().unwrap().to_str().unwrap(), e.kind.as_str()))
+    .collect();
+  kinds.sort();
+  assert_eq!(
+    kinds,
+    [("blank", "empty"), ("no-ids", "missing_level_ids"), ("no-valid-ids", "empty"), ("syntax", "json")]
+  );
+  let syntax = check_manifest_file(&overlay.path().join("syntax/manifest.json")).unwrap_err();
+  assert_eq!((syntax.line, syntax.column), (Some(3), Some(18)));
+  assert!(listing.errors.iter().filter(|e| e.kind != "json").all(|e| e.line.is_none()));
+
+  let checked = validate_collections_dir(overlay.path()).unwrap();
+  assert_eq!(checked.manifests.len(), 1);
+  assert_eq!(checked.errors.len(), 4);
+}
+
+#[test]
+fn validate_rejects_dirs_without_valid_manifests() {
+  let root = tempfile::tempdir().unwrap();
+  assert!(validate_collections_dir(&root.path().join("missing")).is_err());
+  let err = validate_collections_dir(root.path()).unwrap_err();
+  assert!(err.contains("no manifest.json"), "{}", err);
+
+  fs::create_dir_all(root.path().join("bad")).unwrap();
+  fs::write(root.path().join("bad/manifest.json"), "{\n  oops").unwrap();
+  let err = validate_collections_dir(root.path()).unwrap_err();
+  assert!(err.contains("[json]") && err.contains("line 2"), "{}", err);
+}
@@ -0,0 +1,299 @@
+//! 本地 HTTP 替身：模拟下载站的 `/verify_captcha`、`/get_download_link` 与文件服务器。
+
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use niconico_app_lib::downloader::{self, ProgressSink};
+use niconico_app_lib::models::{DownloadTaskInput, TaskEvent, TaskState};
+use niconico_app_lib::{InnerState, TaskControl};
+use parking_lot::Mutex;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+  pub method: String,
+  pub path: String,
+  pub query: HashMap<String, String>,
+  pub headers: HashMap<String, String>,
+}
+
+impl MockRequest {
+  pub fn header(&self, name: &str) -> Option<&str> {
+    self.headers.get(&name.to_ascii_lowercase()).map(|s| s.as_str())
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+  pub status: u16,
+  pub headers: Vec<(String, String)>,
+  pub body: Vec<u8>,
+  /// 在响应头之前等待的时间。
+  pub delay: Option<Duration>,
+  /// 仅发送前 N 个字节后断开连接（Content-Length 仍为完整长度）。
+  pub truncate_at: Option<usize>,
+}
+
+impl MockResponse {
+  pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+    Self {
+      status,
+      headers: Vec::new(),
+      body: body.into(),
+      delay: None,
+      truncate_at: None,
+    }
+  }
+
+  pub fn json(status: u16, value: serde_json::Value) -> Self {
+    Self::new(status, value.to_string()).header("Content-Type", "application/json")
+  }
+
+  pub fn header(mut self, name: &str, value: &str) -> Self {
+    self.headers.push((name.to_string(), value.to_string()));
+    self
+  }
+
+  pub fn delay(mut self, delay: Duration) -> Self {
+    self.delay = Some(delay);
+    self
+  }
+
+  pub fn truncate_at(mut self, bytes: usize) -> Self {
+    self.truncate_at = Some(bytes);
+    self
+  }
+}
+
+type Handler = dyn Fn(&MockServerCtx, &MockRequest) -> MockResponse + Send + Sync;
+
+pub struct MockServerCtx {
+  pub base_url: String,
+}
+
+pub struct MockServer {
+  pub base_url: String,
+  requests: Arc<Mutex<Vec<MockRequest>>>,
+}
+
+impl MockServer {
+  pub async fn start<F>(handler: F) -> Self
+  where
+    F: Fn(&MockServerCtx, &MockRequest) -> MockResponse + Send + Sync + 'static,
+  {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind mock server");
+    let addr = listener.local_addr().expect("mock server addr");
+    let base_url = format!("http://{}", addr);
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let handler: Arc<Handler> = Arc::new(handler);
+    let ctx = Arc::new(MockServerCtx {
+      base_url: base_url.clone(),
+    });
+
+    let log = requests.clone();
+    tokio::spawn(async move {
+      loop {
+        let Ok((stream, _)) = listener.accept().await else {
+          return;
+        };
+        let handler = handler.clone();
+        let ctx = ctx.clone();
+        let log = log.clone();
+        tokio::spawn(async move {
+          let _ = serve_connection(stream, handler, ctx, log).await;
+        });
+      }
+    });
+
+    Self { base_url, requests }
+  }
+
+  pub fn requests(&self) -> Vec<MockRequest> {
+    self.requests.lock().clone()
+  }
+
+  pub fn requests_to(&self, path: &str) -> Vec<MockRequest> {
+    self.requests().into_iter().filter(|r| r.path == path).collect()
+  }
+}
+
+async fn serve_connection(
+  mut stream: TcpStream,
+  handler: Arc<Handler>,
+  ctx: Arc<MockServerCtx>,
+  log: Arc<Mutex<Vec<MockRequest>>>,
+) -> std::io::Result<()> {
+  let mut buf = Vec::new();
+  let mut chunk = [0u8; 4096];
+  let header_end = loop {
+    let n = stream.read(&mut chunk).await?;
+    if n == 0 {
+      return Ok(());
+    }
+    buf.extend_from_slice(&chunk[..n]);
+    if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+      break pos;
+    }
+  };
+
+  let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+  let mut lines = head.split("\r\n");
+  let request_line = lines.next().unwrap_or_default();
+  let mut parts = request_line.split_whitespace();
+  let method = parts.next().unwrap_or_default().to_string();
+  let target = parts.next().unwrap_or_default().to_string();
+
+  let mut headers = HashMap::new();
+  for line in lines {
+    if let Some((k, v)) = line.split_once(':') {
+      headers.insert(k.trim().to_ascii_lowercase(), v.trim().to_string());
+    }
+  }
+
+  // 读掉请求体，避免客户端写入时连接被重置。
+  let content_length: usize = headers
+    .get("content-length")
+    .and_then(|v| v.parse().ok())
+    .unwrap_or(0);
+  let mut body_read = buf.len() - (header_end + 4);
+  while body_read < content_length {
+    let n = stream.read(&mut chunk).await?;
+    if n == 0 {
+      break;
+    }
+    body_read += n;
+  }
+
+  let (path, query_str) = target.split_once('?').unwrap_or((target.as_str(), ""));
+  let query = query_str
+    .split('&')
+    .filter(|s| !s.is_empty())
+    .filter_map(|kv| kv.split_once('='))
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect();
+
+  let req = MockRequest {
+    method,
+    path: path.to_string(),
+    query,
+    headers,
+  };
+  log.lock().push(req.clone());
+
+  let resp = handler(&ctx, &req);
+  if let Some(delay) = resp.delay {
+    tokio::time::sleep(delay).await;
+  }
+
+  let mut head = format!("HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n", resp.status, resp.body.len());
+  for (k, v) in &resp.headers {
+    head.push_str(&format!("{}: {}\r\n", k, v));
+  }
+  head.push_str("\r\n");
+  stream.write_all(head.as_bytes()).await?;
+
+  let body = match resp.truncate_at {
+    Some(n) => &resp.body[..n.min(resp.body.len())],
+    None => &resp.body[..],
+  };
+  stream.write_all(body).await?;
+  stream.flush().await?;
+  stream.shutdown().await?;
+  Ok(())
+}
+
+/// 构造一个最小的谱面包：`<folder>/maidata.txt`。
+pub fn chart_archive(folder: &str) -> Vec<u8> {
+  chart_archive_with(folder, &[("maidata.txt", format!("&title={}\n&artist=mock\n", folder).as_bytes())])
+}
+
+pub fn chart_archive_with(folder: &str, files: &[(&str, &[u8])]) -> Vec<u8> {
+  let mut cursor = std::io::Cursor::new(Vec::new());
+  {
+    let mut writer = zip::ZipWriter::new(&mut cursor);
+    let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for (name, bytes) in files {
+      writer.start_file(format!("{}/{}", folder, name), options).expect("zip start_file");
+      writer.write_all(bytes).expect("zip write");
+    }
+    writer.finish().expect("zip finish");
+  }
+  cursor.into_inner()
+}
+
+/// 下载站替身的常规行为：key 模式下直接给出指向 `/files/<id>.adx` 的链接。
+pub fn link_ok(ctx: &MockServerCtx, req: &MockRequest) -> MockResponse {
+  let id = req.query.get("id").cloned().unwrap_or_default();
+  MockResponse::json(
+    200,
+    serde_json::json!({ "success": true, "url": format!("{}/files/{}.adx", ctx.base_url, id) }),
+  )
+}
+
+pub fn file_ok(req: &MockRequest) -> MockResponse {
+  let id = req
+    .path
+    .trim_start_matches("/files/")
+    .trim_end_matches(".adx")
+    .to_string();
+  MockResponse::new(200, chart_archive(&id))
+}
+
+pub fn base_input(server: &MockServer, output_dir: &Path, ids: &[&str]) -> DownloadTaskInput {
+  DownloadTaskInput {
+    selected_manifest_paths: Vec::new(),
+    output_dir: output_dir.to_string_lossy().to_string(),
+    connect_sid: "mock-sid".to_string(),
+    auth_mode: "key".to_string(),
+    key: Some("mock-key".to_string()),
+    captcha: None,
+    download_no_bga: false,
+    output_format: "adx".to_string(),
+    auto_bundle: false,
+    bundle_output_path: None,
+    bundle_conflict_policy: None,
+    bundle_max_bytes: None,
+    bundle_max_charts: None,
+    bundle_append: false,
+    upgrade_bga: false,
+    retries: Some(1),
+    request_interval_ms: Some(0),
+    concurrency: Some(1),
+    level_ids: Some(ids.iter().map(|s| s.to_string()).collect()),
+    api_base: Some(server.base_url.clone()),
+    filter: None,
+    metadata_url: None,
+  }
+}
+
+#[derive(Default)]
+pub struct RecordingSink {
+  pub events: Mutex<Vec<TaskEvent>>,
+}
+
+impl ProgressSink for RecordingSink {
+  fn emit(&self, event: TaskEvent) {
+    self.events.lock().push(event);
+  }
+}
+
+pub async fn run_task(input: DownloadTaskInput) -> TaskState {
+  let state = Arc::new(InnerState::default());
+  let task_id = "mock-task".to_string();
+  state
+    .tasks
+    .lock()
+    .insert(task_id.clone(), TaskState::new(task_id.clone()));
+
+  let sink: Arc<dyn ProgressSink> = Arc::new(RecordingSink::default());
+  downloader::run_task(sink, state.clone(), task_id.clone(), input, Arc::new(TaskControl::default())).await;
+
+  let task = state.tasks.lock().get(&task_id).cloned();
+  task.expect("task state")
+}
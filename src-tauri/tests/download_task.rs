@@ -0,0 +1,570 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use common::{
+  base_input, chart_archive, chart_archive_with, file_ok, link_ok, run_task, MockResponse, MockServer, RecordingSink,
+};
+use niconico_app_lib::bundle_index::read_index;
+use niconico_app_lib::downloader;
+use niconico_app_lib::models::TaskState;
+use niconico_app_lib::{InnerState, TaskControl};
+
+#[tokio::test]
+async fn downloads_every_id() {
+  let server = MockServer::start(|ctx, req| match req.path.as_str() {
+    "/get_download_link" => link_ok(ctx, req),
+    _ => file_ok(req),
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+
+  let task = run_task(base_input(&server, out.path(), &["101", "102", "103"])).await;
+
+  assert_eq!(task.status, "completed");
+  assert_eq!(task.total_ids, 3);
+  assert_eq!(task.processed_ids, 3);
+  assert_eq!(task.ok_count, 3);
+  assert_eq!(task.fail_count, 0);
+  assert_eq!(task.new_files_count, 3);
+  for id in ["101", "102", "103"] {
+    let bytes = std::fs::read(out.path().join(format!("{}.adx", id))).unwrap();
+    assert_eq!(bytes, chart_archive(id));
+  }
+
+  let links = server.requests_to("/get_download_link");
+  assert_eq!(links.len(), 3);
+  assert!(links.iter().all(|r| r.query.get("key").map(String::as_str) == Some("mock-key")));
+  assert!(links.iter().all(|r| r.header("cookie") == Some("connect.sid=mock-sid")));
+}
+
+#[tokio::test]
+async fn skips_existing_files() {
+  let server = MockServer::start(|ctx, req| match req.path.as_str() {
+    "/get_download_link" => link_ok(ctx, req),
+    _ => file_ok(req),
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+  std::fs::write(out.path().join("201.adx"), chart_archive("201")).unwrap();
+
+  let task = run_task(base_input(&server, out.path(), &["201", "202"])).await;
+
+  assert_eq!(task.status, "completed");
+  assert_eq!(task.skip_count, 1);
+  assert_eq!(task.ok_count, 1);
+  assert_eq!(server.requests_to("/get_download_link").len(), 1);
+}
+
+#[tokio::test]
+async fn exchanges_captcha_for_key() {
+  let server = MockServer::start(|ctx, req| match req.path.as_str() {
+    "/verify_captcha" => MockResponse::json(200, serde_json::json!({ "success": true, "key": "from-captcha" })),
+    "/get_download_link" => link_ok(ctx, req),
+    _ => file_ok(req),
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+  let mut input = base_input(&server, out.path(), &["301"]);
+  input.auth_mode = "captcha".to_string();
+  input.key = None;
+  input.captcha = Some("1234".to_string());
+
+  let task = run_task(input).await;
+
+  assert_eq!(task.status, "completed");
+  assert_eq!(task.ok_count, 1);
+  let link = &server.requests_to("/get_download_link")[0];
+  assert_eq!(link.query.get("key").map(String::as_str), Some("from-captcha"));
+}
+
+#[tokio::test]
+async fn fails_task_when_captcha_is_rejected() {
+  let server = MockServer::start(|_, req| match req.path.as_str() {
+    "/verify_captcha" => MockResponse::json(200, serde_json::json!({ "success": false, "message": "wrong code" })),
+    _ => MockResponse::new(404, ""),
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+  let mut input = base_input(&server, out.path(), &["401"]);
+  input.auth_mode = "captcha".to_string();
+  input.captcha = Some("0000".to_string());
+
+  let task = run_task(input).await;
+
+  assert_eq!(task.status, "failed");
+  assert!(task.message.unwrap().contains("wrong code"));
+  assert!(server.requests_to("/get_download_link").is_empty());
+}
+
+#[tokio::test]
+async fn reports_http_error_body() {
+  let server = MockServer::start(|_, _| {
+    MockResponse::json(429, serde_json::json!({ "success": false, "message": "too many requests" }))
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+
+  let task = run_task(base_input(&server, out.path(), &["501"])).await;
+
+  assert_eq!(task.status, "completed");
+  assert_eq!(task.fail_count, 1);
+  let reason = &task.fail_items[0].reason;
+  assert!(reason.starts_with("link_fail:"), "{}", reason);
+  assert!(reason.contains("429"), "{}", reason);
+  assert!(reason.contains("too many requests"), "{}", reason);
+}
+
+#[tokio::test]
+async fn reports_malformed_json() {
+  let server = MockServer::start(|_, _| MockResponse::new(200, "<html>gateway</html>")).await;
+  let out = tempfile::tempdir().unwrap();
+
+  let task = run_task(base_input(&server, out.path(), &["601"])).await;
+
+  assert_eq!(task.fail_count, 1);
+  let reason = &task.fail_items[0].reason;
+  assert!(reason.starts_with("link_fail:"), "{}", reason);
+  assert!(reason.contains("parse failed"), "{}", reason);
+}
+
+#[tokio::test]
+async fn reports_success_false() {
+  let server = MockServer::start(|_, _| {
+    MockResponse::json(200, serde_json::json!({ "success": false, "message": "chart not found" }))
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+
+  let task = run_task(base_input(&server, out.path(), &["701"])).await;
+
+  assert_eq!(task.fail_count, 1);
+  assert_eq!(task.fail_items[0].id, "701");
+  assert_eq!(task.fail_items[0].reason, "link_fail: get_download_link failed for 701: chart not found");
+  assert!(!out.path().join("701.adx").exists());
+}
+
+#[tokio::test]
+async fn truncated_stream_fails_and_keeps_partial_file() {
+  let server = MockServer::start(|ctx, req| match req.path.as_str() {
+    "/get_download_link" => link_ok(ctx, req),
+    _ => MockResponse::new(200, chart_archive("801")).truncate_at(10),
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+
+  let task = run_task(base_input(&server, out.path(), &["801"])).await;
+
+  assert_eq!(task.status, "completed");
+  assert_eq!(task.fail_count, 1);
+  assert!(task.fail_items[0].reason.starts_with("download_fail:"), "{}", task.fail_items[0].reason);
+  assert!(!out.path().join("801.adx").exists());
+  assert!(out.path().join("801.adx.part").exists());
+}
+
+#[tokio::test]
+async fn retry_resumes_truncated_download_with_range() {
+  let calls = Arc::new(AtomicUsize::new(0));
+  let calls_in_handler = calls.clone();
+  let server = MockServer::start(move |ctx, req| {
+    if req.path == "/get_download_link" {
+      return link_ok(ctx, req);
+    }
+    let full = chart_archive("901");
+    let half = full.len() / 2;
+    if calls_in_handler.fetch_add(1, Ordering::SeqCst) == 0 {
+      return MockResponse::new(200, full).header("ETag", "\"v1\"").truncate_at(half);
+    }
+    let range = req.header("range").unwrap_or_default().to_string();
+    let start: usize = range.trim_start_matches("bytes=").trim_end_matches('-').parse().unwrap();
+    let total = full.len();
+    MockResponse::new(206, full[start..].to_vec())
+      .header("ETag", "\"v1\"")
+      .header("Content-Range", &format!("bytes {}-{}/{}", start, total - 1, total))
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+  let mut input = base_input(&server, out.path(), &["901"]);
+  input.retries = Some(2);
+
+  let task = run_task(input).await;
+
+  assert_eq!(task.ok_count, 1, "{:?}", task.fail_items);
+  assert_eq!(std::fs::read(out.path().join("901.adx")).unwrap(), chart_archive("901"));
+  let files = server.requests_to("/files/901.adx");
+  assert_eq!(files.len(), 2);
+  assert_eq!(files[1].header("if-range"), Some("\"v1\""));
+  assert!(!out.path().join("901.adx.part").exists());
+  assert!(!out.path().join("901.adx.part.meta").exists());
+}
+
+#[tokio::test]
+async fn verifies_complete_partial_file_before_accepting_416() {
+  let full = chart_archive("951");
+  let server = MockServer::start(|ctx, req| {
+    if req.path == "/get_download_link" {
+      return link_ok(ctx, req);
+    }
+    if req.header("range").is_some() {
+      return MockResponse::new(416, "");
+    }
+    file_ok(req)
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+  // 长度与服务端文件一致但内容损坏的残留 .part。
+  std::fs::write(out.path().join("951.adx.part"), vec![0u8; full.len()]).unwrap();
+  let meta = serde_json::json!({ "etag": "\"v1\"", "totalLen": full.len() });
+  std::fs::write(out.path().join("951.adx.part.meta"), meta.to_string()).unwrap();
+  let mut input = base_input(&server, out.path(), &["951"]);
+  input.retries = Some(2);
+
+  let task = run_task(input).await;
+
+  assert_eq!(task.ok_count, 1, "{:?}", task.fail_items);
+  assert_eq!(std::fs::read(out.path().join("951.adx")).unwrap(), full);
+  let files = server.requests_to("/files/951.adx");
+  assert_eq!(files.len(), 2);
+  assert!(files[0].header("range").is_some());
+  assert!(files[1].header("range").is_none());
+  assert!(!out.path().join("951.adx.part").exists());
+  assert!(!out.path().join("951.adx.part.meta").exists());
+}
+
+#[tokio::test]
+async fn slow_file_host_does_not_block_other_workers() {
+  let server = MockServer::start(|ctx, req| match req.path.as_str() {
+    "/get_download_link" => link_ok(ctx, req),
+    "/files/1001.adx" => file_ok(req).delay(Duration::from_millis(600)),
+    _ => file_ok(req),
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+  let mut input = base_input(&server, out.path(), &["1001", "1002", "1003", "1004"]);
+  input.concurrency = Some(2);
+
+  let started = Instant::now();
+  let task = run_task(input).await;
+
+  assert_eq!(task.ok_count, 4);
+  assert_eq!(task.processed_ids, 4);
+  // 串行时约等于 600ms + 其余请求；并发下其余三个应在慢请求期间完成。
+  assert!(started.elapsed() < Duration::from_millis(1200));
+}
+
+#[tokio::test]
+async fn link_requests_honour_global_interval() {
+  let server = MockServer::start(|ctx, req| match req.path.as_str() {
+    "/get_download_link" => link_ok(ctx, req),
+    _ => file_ok(req),
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+  let mut input = base_input(&server, out.path(), &["1101", "1102", "1103"]);
+  input.concurrency = Some(3);
+  input.request_interval_ms = Some(150);
+
+  let started = Instant::now();
+  let task = run_task(input).await;
+
+  assert_eq!(task.ok_count, 3);
+  assert!(started.elapsed() >= Duration::from_millis(300));
+}
+
+#[tokio::test]
+async fn auto_bundle_covers_new_files() {
+  let server = MockServer::start(|ctx, req| match req.path.as_str() {
+    "/get_download_link" => link_ok(ctx, req),
+    _ => file_ok(req),
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+  let bundle_path = out.path().join("bundle.adx");
+  let mut input = base_input(&server, out.path(), &["1201", "1202"]);
+  input.auto_bundle = true;
+  input.bundle_output_path = Some(bundle_path.to_string_lossy().to_string());
+
+  let task = run_task(input).await;
+
+  assert_eq!(task.status, "completed");
+  assert_eq!(task.bundle_output_paths, vec![bundle_path.to_string_lossy().to_string()]);
+  let archive = zip::ZipArchive::new(std::fs::File::open(&bundle_path).unwrap()).unwrap();
+  let mut names: Vec<_> = archive.file_names().map(str::to_string).collect();
+  names.sort();
+  assert_eq!(names, vec!["1201/1201/maidata.txt", "1202/1202/maidata.txt", "bundle_index.json"]);
+}
+
+#[tokio::test]
+async fn auto_bundle_index_records_level_ids() {
+  let server = MockServer::start(|ctx, req| match req.path.as_str() {
+    "/get_download_link" => link_ok(ctx, req),
+    _ => file_ok(req),
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+  let bundle_path = out.path().join("bundle.adx");
+  let mut input = base_input(&server, out.path(), &["1251", "dx:1252"]);
+  input.auto_bundle = true;
+  input.bundle_output_path = Some(bundle_path.to_string_lossy().to_string());
+
+  let task = run_task(input).await;
+
+  assert_eq!(task.status, "completed");
+  assert!(out.path().join("dx_1252.adx").exists());
+  let mut archive = zip::ZipArchive::new(std::fs::File::open(&bundle_path).unwrap()).unwrap();
+  let index = read_index(&mut archive).unwrap().unwrap();
+  let mut ids: Vec<_> = index.charts.iter().map(|c| (c.level_id.as_str(), c.source_file.as_str())).collect();
+  ids.sort();
+  assert_eq!(ids, [("1251", "1251.adx"), ("dx:1252", "dx_1252.adx")]);
+}
+
+#[tokio::test]
+async fn rejects_download_that_is_not_an_archive() {
+  let server = MockServer::start(|ctx, req| match req.path.as_str() {
+    "/get_download_link" => link_ok(ctx, req),
+    _ => MockResponse::new(200, "<html>login required</html>"),
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+  let mut input = base_input(&server, out.path(), &["1301"]);
+  input.retries = Some(2);
+
+  let task = run_task(input).await;
+
+  assert_eq!(task.fail_count, 1);
+  assert!(task.fail_items[0].reason.starts_with("verify_fail:"), "{}", task.fail_items[0].reason);
+  assert_eq!(server.requests_to("/files/1301.adx").len(), 2);
+  assert!(!out.path().join("1301.adx").exists());
+  assert!(!out.path().join("1301.adx.part").exists());
+}
+
+#[tokio::test]
+async fn rejects_archive_without_maidata() {
+  let server = MockServer::start(|ctx, req| match req.path.as_str() {
+    "/get_download_link" => link_ok(ctx, req),
+    _ => MockResponse::new(200, chart_archive_with("1401", &[("track.mp3", b"xx")])),
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+
+  let task = run_task(base_input(&server, out.path(), &["1401"])).await;
+
+  assert_eq!(task.fail_count, 1);
+  assert!(task.fail_items[0].reason.contains("maidata.txt"), "{}", task.fail_items[0].reason);
+  assert!(!out.path().join("1401.adx").exists());
+}
+
+#[tokio::test]
+async fn redownloads_corrupt_existing_file() {
+  let server = MockServer::start(|ctx, req| match req.path.as_str() {
+    "/get_download_link" => link_ok(ctx, req),
+    _ => file_ok(req),
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+  std::fs::write(out.path().join("1501.adx"), b"garbage").unwrap();
+
+  let task = run_task(base_input(&server, out.path(), &["1501"])).await;
+
+  assert_eq!(task.skip_count, 0);
+  assert_eq!(task.ok_count, 1);
+  assert_eq!(std::fs::read(out.path().join("1501.adx")).unwrap(), chart_archive("1501"));
+}
+
+#[tokio::test]
+async fn filters_ids_by_chart_metadata() {
+  let server = MockServer::start(|ctx, req| match req.path.as_str() {
+    "/chart_metadata" => {
+      let meta = |levels: &[(&str, &str)]| {
+        let difficulties: Vec<_> = levels
+          .iter()
+          .map(|(name, level)| serde_json::json!({ "name": name, "level": level, "hasChart": true }))
+          .collect();
+        serde_json::json!({ "title": "t", "difficulties": difficulties })
+      };
+      match req.query.get("id").map(String::as_str) {
+        Some("901") => MockResponse::json(200, meta(&[("master", "13+"), ("remaster", "14")])),
+        Some("902") => MockResponse::json(200, meta(&[("master", "12")])),
+        Some("903") => MockResponse::json(200, meta(&[("master", "13"), ("utage", "14?")])),
+        _ => MockResponse::new(404, "not found"),
+      }
+    }
+    "/get_download_link" => link_ok(ctx, req),
+    _ => file_ok(req),
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+  let mut input = base_input(&server, out.path(), &["901", "902", "903", "904"]);
+  input.filter = Some("level>=13, !utage".to_string());
+  input.metadata_url = Some(format!("{}/chart_metadata", server.base_url));
+
+  let task = run_task(input).await;
+
+  assert_eq!(task.status, "completed");
+  assert_eq!(task.total_ids, 2);
+  assert!(out.path().join("901.adx").exists());
+  assert!(out.path().join("904.adx").exists());
+  let report = task.filter_report.unwrap();
+  assert_eq!((report.input_count, report.kept_count, report.unknown_count), (4, 2, 1));
+  let removed: Vec<_> = report.rules.iter().map(|r| (r.rule.as_str(), r.removed)).collect();
+  assert_eq!(removed, vec![("level>=13", 1), ("!utage", 1)]);
+}
+
+#[tokio::test]
+async fn fails_task_on_invalid_filter() {
+  let server = MockServer::start(|_, _| MockResponse::new(500, "unused")).await;
+  let out = tempfile::tempdir().unwrap();
+  let mut input = base_input(&server, out.path(), &["911"]);
+  input.filter = Some("level>>13".to_string());
+
+  let task = run_task(input).await;
+
+  assert_eq!(task.status, "failed");
+  assert!(server.requests().is_empty());
+}
+
+#[tokio::test]
+async fn upgrades_nobga_files_in_place() {
+  let server = MockServer::start(|ctx, req| match req.path.as_str() {
+    "/get_download_link" => {
+      let id = req.query.get("id").cloned().unwrap_or_default();
+      let kind = req.query.get("type").cloned().unwrap_or_default();
+      MockResponse::json(
+        200,
+        serde_json::json!({ "success": true, "url": format!("{}/files/{}/{}.adx", ctx.base_url, kind, id) }),
+      )
+    }
+    path => {
+      let (kind, file) = path.trim_start_matches("/files/").split_once('/').unwrap_or_default();
+      let id = file.trim_end_matches(".adx");
+      if kind == "bga" {
+        MockResponse::new(200, chart_archive_with(id, &[("maidata.txt", b"&title=x\n"), ("pv.mp4", b"video")]))
+      } else {
+        MockResponse::new(200, chart_archive(id))
+      }
+    }
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+  let has_video = |id: &str| {
+    let file = std::fs::File::open(out.path().join(format!("{}.adx", id))).unwrap();
+    zip::ZipArchive::new(file).unwrap().by_name(&format!("{}/pv.mp4", id)).is_ok()
+  };
+  let mut input = base_input(&server, out.path(), &["1601"]);
+  input.download_no_bga = true;
+  assert_eq!(run_task(input).await.ok_count, 1);
+  std::fs::write(
+    out.path().join("1602.adx"),
+    chart_archive_with("1602", &[("maidata.txt", b"&title=x\n"), ("pv.mp4", b"video")]),
+  )
+  .unwrap();
+  std::fs::write(out.path().join("1603.adx"), chart_archive("1603")).unwrap();
+
+  let mut input = base_input(&server, out.path(), &["1601", "1602", "1603"]);
+  input.upgrade_bga = true;
+  let task = run_task(input).await;
+
+  assert_eq!(task.status, "completed");
+  assert_eq!((task.ok_count, task.skip_count, task.upgraded_count), (2, 1, 2));
+  assert!(["1601", "1602", "1603"].iter().all(|id| has_video(id)));
+  let variants: serde_json::Value =
+    serde_json::from_slice(&std::fs::read(out.path().join("chart_variants.json")).unwrap()).unwrap();
+  assert_eq!(variants["1601"]["variant"], "bga");
+  assert_eq!(variants["1603"]["variant"], "bga");
+  assert!(variants.get("1602").is_none());
+  let leftovers = std::fs::read_dir(out.path()).unwrap().count();
+  assert_eq!(leftovers, 4);
+
+  let mut input = base_input(&server, out.path(), &["1601"]);
+  input.upgrade_bga = true;
+  input.download_no_bga = true;
+  assert_eq!(run_task(input).await.status, "failed");
+}
+
+#[tokio::test]
+async fn records_variants_when_cancelled() {
+  let server = MockServer::start(|ctx, req| match req.path.as_str() {
+    "/get_download_link" => link_ok(ctx, req),
+    "/files/1702.adx" => file_ok(req).delay(Duration::from_millis(1500)),
+    _ => file_ok(req),
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+  let mut input = base_input(&server, out.path(), &["1701", "1702"]);
+  input.concurrency = Some(2);
+  input.request_interval_ms = Some(0);
+  let state = Arc::new(InnerState::default());
+  let task_id = "cancelled-task".to_string();
+  state.tasks.lock().insert(task_id.clone(), TaskState::new(task_id.clone()));
+  let control = Arc::new(TaskControl::default());
+
+  let run = tokio::spawn(downloader::run_task(
+    Arc::new(RecordingSink::default()),
+    state.clone(),
+    task_id.clone(),
+    input,
+    control.clone(),
+  ));
+  tokio::time::sleep(Duration::from_millis(500)).await;
+  control.cancel();
+  run.await.unwrap();
+
+  assert_eq!(state.tasks.lock()[&task_id].status, "cancelled");
+  let variants: serde_json::Value =
+    serde_json::from_slice(&std::fs::read(out.path().join("chart_variants.json")).unwrap()).unwrap();
+  assert_eq!(variants["1701"]["variant"], "bga");
+}
+
+#[tokio::test]
+async fn paused_task_takes_no_new_ids_until_resumed() {
+  let server = MockServer::start(|ctx, req| match req.path.as_str() {
+    "/get_download_link" => link_ok(ctx, req),
+    "/files/1801.adx" | "/files/1802.adx" => file_ok(req).delay(Duration::from_millis(800)),
+    _ => file_ok(req),
+  })
+  .await;
+  let out = tempfile::tempdir().unwrap();
+  let mut input = base_input(&server, out.path(), &["1801", "1802", "1803", "1804"]);
+  input.concurrency = Some(2);
+  input.request_interval_ms = Some(0);
+  let state = Arc::new(InnerState::default());
+  let task_id = "paused-task".to_string();
+  state.tasks.lock().insert(task_id.clone(), TaskState::new(task_id.clone()));
+  let control = Arc::new(TaskControl::default());
+
+  let run = tokio::spawn(downloader::run_task(
+    Arc::new(RecordingSink::default()),
+    state.clone(),
+    task_id.clone(),
+    input,
+    control.clone(),
+  ));
+  tokio::time::sleep(Duration::from_millis(300)).await;
+  control.pause();
+
+  // 暂停前已开始的两个下载照常完成，之后不再领取新的 ID。
+  tokio::time::sleep(Duration::from_millis(1500)).await;
+  assert_eq!(server.requests_to("/get_download_link").len(), 2);
+  assert_eq!(state.tasks.lock()[&task_id].processed_ids, 2);
+  assert!(out.path().join("1801.adx").is_file() && out.path().join("1802.adx").is_file());
+  assert!(!out.path().join("1803.adx").exists());
+  assert!(!run.is_finished());
+
+  control.resume();
+  run.await.unwrap();
+
+  let task = state.tasks.lock()[&task_id].clone();
+  assert_eq!(task.status, "completed");
+  assert_eq!((task.processed_ids, task.ok_count, task.fail_items.len()), (4, 4, 0));
+  assert_eq!(server.requests_to("/get_download_link").len(), 4);
+  let mut names: Vec<_> = std::fs::read_dir(out.path())
+    .unwrap()
+    .flatten()
+    .map(|e| e.file_name().to_string_lossy().to_string())
+    .filter(|n| n.ends_with(".adx") || n.contains(".part"))
+    .collect();
+  names.sort();
+  assert_eq!(names, ["1801.adx", "1802.adx", "1803.adx", "1804.adx"]);
+}
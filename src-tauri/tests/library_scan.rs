@@ -0,0 +1,57 @@
+mod common;
+
+use std::fs;
+
+use common::chart_archive;
+use niconico_app_lib::bundler::{build_bundle_from_files, BundleOptions};
+use niconico_app_lib::library::scan_library;
+
+#[test]
+fn reports_problems_and_missing_ids() {
+  let dir = tempfile::tempdir().unwrap();
+  let lib = dir.path().join("lib");
+  fs::create_dir_all(&lib).unwrap();
+  fs::write(lib.join("1.adx"), chart_archive("1")).unwrap();
+  fs::write(lib.join("2.zip"), chart_archive("2")).unwrap();
+  fs::write(lib.join("3.adx"), b"").unwrap();
+  fs::write(lib.join("4.adx"), b"not a zip").unwrap();
+  fs::write(lib.join("5.adx.part"), b"half").unwrap();
+  fs::write(lib.join("5.adx.part.meta"), br#"{"totalLen":8}"#).unwrap();
+  fs::write(lib.join("bundle.adx.0123abcd.tmp"), b"partial").unwrap();
+  fs::write(lib.join("a_b.adx"), chart_archive("a:b")).unwrap();
+  // 文件名本身是合法 ID 的整合包也不能算作谱面。
+  build_bundle_from_files(&[lib.join("1.adx"), lib.join("2.zip")], &lib.join("all.adx"), &BundleOptions::default())
+    .unwrap();
+
+  let manifest = dir.path().join("pack").join("manifest.json");
+  fs::create_dir_all(manifest.parent().unwrap()).unwrap();
+  fs::write(&manifest, r#"{"name":"Pack","levelIds":[1,2,3,4,5,"a:b"]}"#).unwrap();
+
+  let report = scan_library(&lib, &[manifest.to_string_lossy().to_string()]).unwrap();
+
+  assert_eq!(report.ok_count, 3);
+  assert_eq!(report.empty_count, 1);
+  assert_eq!(report.corrupt_count, 1);
+  let stale: Vec<_> = report
+    .stale_parts
+    .iter()
+    .map(|p| std::path::Path::new(p).file_name().unwrap().to_str().unwrap())
+    .collect();
+  assert_eq!(stale, ["5.adx.part", "5.adx.part.meta", "bundle.adx.0123abcd.tmp"]);
+
+  let bundles: Vec<_> = report
+    .bundles
+    .iter()
+    .map(|p| std::path::Path::new(p).file_name().unwrap().to_str().unwrap())
+    .collect();
+  assert_eq!(bundles, ["all.adx"]);
+  assert!(report.files.iter().all(|f| f.file_name != "all.adx"));
+
+  let ab = report.files.iter().find(|f| f.file_name == "a_b.adx").unwrap();
+  assert_eq!(ab.level_id.as_deref(), Some("a:b"));
+
+  assert_eq!(report.manifests.len(), 1);
+  assert_eq!(report.manifests[0].name, "Pack");
+  assert_eq!(report.manifests[0].present, 3);
+  assert_eq!(report.missing_ids, vec!["3", "4", "5"]);
+}
@@ -0,0 +1,91 @@
+use std::fs;
+
+use niconico_app_lib::models::{DownloadTaskInput, TaskRecord, TaskState};
+use niconico_app_lib::task_store::{redact_input, TaskStore};
+
+const TASK_ID: &str = "3f2b8c1e-5d4a-4e9b-9c7d-0a1b2c3d4e5f";
+
+fn sample_input() -> DownloadTaskInput {
+  serde_json::from_value(serde_json::json!({
+    "selectedManifestPaths": ["/lists/picks/manifest.json"],
+    "outputDir": "/charts",
+    "connectSid": "s%3Asecret-session",
+    "authMode": "captcha",
+    "key": "secret-key",
+    "captcha": "1234",
+    "downloadNoBga": true,
+    "outputFormat": "adx",
+    "autoBundle": false,
+    "levelIds": ["101", "102"],
+  }))
+  .unwrap()
+}
+
+fn sample_record(task_id: &str) -> TaskRecord {
+  let mut state = TaskState::new(task_id.to_string());
+  state.status = "completed".to_string();
+  state.total_ids = 2;
+  state.ok_count = 2;
+  state.logs = vec!["任务完成".to_string()];
+  TaskRecord {
+    input: redact_input(&sample_input()),
+    state,
+  }
+}
+
+#[test]
+fn saves_loads_and_deletes_records() {
+  let dir = tempfile::tempdir().unwrap();
+  let store = TaskStore::open(dir.path().join("tasks")).unwrap();
+  let record = sample_record(TASK_ID);
+
+  store.save(&record).unwrap();
+  let loaded = store.load_all();
+
+  assert_eq!(loaded.len(), 1);
+  assert_eq!(
+    serde_json::to_value(&loaded[0]).unwrap(),
+    serde_json::to_value(&record).unwrap()
+  );
+  assert!(dir.path().join("tasks").join(format!("{}.json", TASK_ID)).is_file());
+
+  // task id 只接受 uuid 形式，不能拼出目录之外的路径。
+  assert!(store.save(&sample_record("../escape")).is_err());
+  assert!(store.delete("../tasks").is_err());
+  assert!(store.delete("").is_err());
+
+  store.delete(TASK_ID).unwrap();
+  store.delete(TASK_ID).unwrap();
+  assert!(store.load_all().is_empty());
+}
+
+#[test]
+fn redacts_credentials_but_keeps_task_parameters() {
+  let input = sample_input();
+
+  let redacted = redact_input(&input);
+
+  assert_eq!(redacted.connect_sid, "");
+  assert_eq!((redacted.key.as_deref(), redacted.captcha.as_deref()), (None, None));
+  assert_eq!(redacted.output_dir, input.output_dir);
+  assert_eq!(redacted.selected_manifest_paths, input.selected_manifest_paths);
+  assert_eq!(redacted.level_ids, input.level_ids);
+  assert!(redacted.download_no_bga);
+  let saved = serde_json::to_string(&redacted).unwrap();
+  assert!(!saved.contains("secret"));
+}
+
+#[test]
+fn skips_corrupt_records_and_keeps_the_rest() {
+  let dir = tempfile::tempdir().unwrap();
+  let store = TaskStore::open(dir.path().to_path_buf()).unwrap();
+  store.save(&sample_record(TASK_ID)).unwrap();
+  fs::write(dir.path().join("0123abcd.json"), "{ \"input\": ").unwrap();
+  fs::write(dir.path().join("notes.txt"), "not a record").unwrap();
+
+  let loaded = store.load_all();
+
+  assert_eq!(loaded.len(), 1);
+  assert_eq!(loaded[0].state.task_id, TASK_ID);
+  assert_eq!(loaded[0].state.status, "completed");
+}
@@ -46,10 +46,13 @@ const INITIAL_FORM_VALUES: DownloadFormValues = {
   captcha: "",
   outputFormat: "adx",
   downloadNoBga: false,
+  upgradeBga: false,
   autoBundle: false,
   bundleOutputPath: "",
+  bundleMaxMb: 0,
   retries: 3,
   requestIntervalMs: 1000,
+  concurrency: 3,
 };
 
 type PanelLoadingProps = {
@@ -136,6 +139,22 @@ function App() {
     }
   };
 
+  const handleDownloadAdded = async () => {
+    const addedIds = collections.diff?.addedIds ?? [];
+    if (!addedIds.length) {
+      return;
+    }
+
+    try {
+      const values = await form.validateFields();
+      await taskRunner.runTask({ ...toDownloadInput([], values), levelIds: addedIds });
+    } catch (e) {
+      const line = `启动任务失败: ${String(e)}`;
+      taskRunner.appendLog(line);
+      message.error(line);
+    }
+  };
+
   const handleStart = () => {
     void form.submit();
   };
@@ -148,6 +167,22 @@ function App() {
     });
   };
 
+  const handlePause = () => {
+    taskRunner.requestPause().catch((e) => {
+      const line = `暂停任务失败: ${String(e)}`;
+      taskRunner.appendLog(line);
+      message.error(line);
+    });
+  };
+
+  const handleResume = () => {
+    taskRunner.requestResume().catch((e) => {
+      const line = `恢复任务失败: ${String(e)}`;
+      taskRunner.appendLog(line);
+      message.error(line);
+    });
+  };
+
   return (
     <AppShell header={<TopHeader />}>
       <Space direction="vertical" size={16} style={{ width: "100%" }}>
@@ -158,6 +193,8 @@ function App() {
             selectedCount={collections.selectedCount}
             dedupSelectedCount={collections.dedupSelectedCount}
             loading={collections.loading}
+            addedIdCount={collections.diff?.addedIds.length ?? 0}
+            downloadAddedDisabled={taskRunner.busy}
             refreshDir={collections.refreshDir}
             onRefreshDirChange={collections.setRefreshDir}
             onBrowseRefreshDir={handleBrowseRefreshDir}
@@ -166,6 +203,9 @@ function App() {
             onSelectAll={collections.selectAll}
             onClearAll={collections.clearAll}
             onTogglePath={collections.togglePath}
+            onDownloadAdded={() => {
+              void handleDownloadAdded();
+            }}
           />
         </Suspense>
 
@@ -192,9 +232,12 @@ function App() {
                   <TaskActionsBar
                     busy={taskRunner.busy}
                     taskId={taskRunner.taskId}
+                    paused={taskRunner.taskState?.status === "paused"}
                     selectedCount={collections.dedupSelectedCount}
                     onStart={handleStart}
                     onCancel={handleCancel}
+                    onPause={handlePause}
+                    onResume={handleResume}
                   />
                 </Suspense>
               </div>
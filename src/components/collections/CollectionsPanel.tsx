@@ -9,6 +9,8 @@ type CollectionsPanelProps = {
   selectedCount: number;
   dedupSelectedCount: number;
   loading: boolean;
+  addedIdCount: number;
+  downloadAddedDisabled: boolean;
   refreshDir: string;
   onRefreshDirChange: (value: string) => void;
   onBrowseRefreshDir: () => Promise<void>;
@@ -17,6 +19,7 @@ type CollectionsPanelProps = {
   onSelectAll: () => void;
   onClearAll: () => void;
   onTogglePath: (path: string) => void;
+  onDownloadAdded: () => void;
 };
 
 export function CollectionsPanel({
@@ -25,6 +28,8 @@ export function CollectionsPanel({
   selectedCount,
   dedupSelectedCount,
   loading,
+  addedIdCount,
+  downloadAddedDisabled,
   refreshDir,
   onRefreshDirChange,
   onBrowseRefreshDir,
@@ -33,6 +38,7 @@ export function CollectionsPanel({
   onSelectAll,
   onClearAll,
   onTogglePath,
+  onDownloadAdded,
 }: CollectionsPanelProps) {
   return (
     <Card
@@ -64,6 +70,9 @@ export function CollectionsPanel({
           <Typography.Text>已选清单: {selectedCount}</Typography.Text>
           <Typography.Text type="secondary">当前已选清单数: {dedupSelectedCount}</Typography.Text>
           <Typography.Text type="secondary">去重策略在后端执行</Typography.Text>
+          <Button onClick={onDownloadAdded} disabled={downloadAddedDisabled || addedIdCount === 0}>
+            仅下载新增谱面（{addedIdCount}）
+          </Button>
         </Space>
 
         <ManifestTable
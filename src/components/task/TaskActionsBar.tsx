@@ -3,17 +3,23 @@ import { Button, Card, Space, Tag, Typography } from "antd";
 type TaskActionsBarProps = {
   busy: boolean;
   taskId: string | null;
+  paused: boolean;
   selectedCount: number;
   onStart: () => void;
   onCancel: () => void;
+  onPause: () => void;
+  onResume: () => void;
 };
 
 export function TaskActionsBar({
   busy,
   taskId,
+  paused,
   selectedCount,
   onStart,
   onCancel,
+  onPause,
+  onResume,
 }: TaskActionsBarProps) {
   return (
     <Card className="panel-card">
@@ -21,6 +27,9 @@ export function TaskActionsBar({
         <Button type="primary" size="large" onClick={onStart} loading={busy}>
           {busy ? "任务进行中" : "开始下载"}
         </Button>
+        <Button size="large" disabled={!taskId || !busy} onClick={paused ? onResume : onPause}>
+          {paused ? "恢复任务" : "暂停任务"}
+        </Button>
         <Button danger size="large" disabled={!taskId || !busy} onClick={onCancel}>
           取消任务
         </Button>
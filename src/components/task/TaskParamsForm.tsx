@@ -172,7 +172,7 @@ export function TaskParamsForm({
 
           <Col xs={12} md={8}>
             <Form.Item
-              label={labelWithTip("请求间隔(ms)", "每个谱面请求之间的等待时间；默认 1000ms，遇到 429 建议继续增大。")}
+              label={labelWithTip("请求间隔(ms)", "获取下载链接请求之间的全局最小间隔（所有并发共享）；默认 1000ms，遇到 429 建议继续增大。")}
               name="requestIntervalMs"
               rules={[{ required: true }]}
             >
@@ -180,6 +180,16 @@ export function TaskParamsForm({
             </Form.Item>
           </Col>
 
+          <Col xs={12} md={8}>
+            <Form.Item
+              label={labelWithTip("并发数", "同时进行的谱面下载数量；链接请求仍受请求间隔限速。")}
+              name="concurrency"
+              rules={[{ required: true }]}
+            >
+              <InputNumber min={1} max={16} style={{ width: "100%" }} />
+            </Form.Item>
+          </Col>
+
           <Col xs={12} md={8}>
             <Form.Item
               label={labelWithTip("下载不包含 BGA", "勾选后请求 no-BGA 资源，BGA 即谱面 PV（.mp4）。")}
@@ -190,6 +200,16 @@ export function TaskParamsForm({
             </Form.Item>
           </Col>
 
+          <Col xs={12} md={8}>
+            <Form.Item
+              label={labelWithTip("升级为 BGA 版本", "已下载的无 BGA 版本会重新下载 BGA 版本，校验通过后替换原文件。")}
+              name="upgradeBga"
+              valuePropName="checked"
+            >
+              <Switch />
+            </Form.Item>
+          </Col>
+
           <Col xs={12} md={8}>
             <Form.Item
               label={labelWithTip("下载后自动整合", "下载完成后直接整合本次新增文件为单个 .adx。")}
@@ -214,6 +234,15 @@ export function TaskParamsForm({
               />
             </Form.Item>
           </Col>
+
+          <Col xs={12} md={8}>
+            <Form.Item
+              label={labelWithTip("分包上限（MB）", "超过上限时拆分为 *_part01.adx、*_part02.adx 等；0 表示不分包。")}
+              name="bundleMaxMb"
+            >
+              <InputNumber min={0} disabled={!autoBundle} style={{ width: "100%" }} />
+            </Form.Item>
+          </Col>
         </Row>
       </Form>
     </Card>
@@ -15,6 +15,8 @@ function statusColor(status: TaskStatusViewModel["status"]): string {
       return "warning";
     case "running":
       return "processing";
+    case "paused":
+      return "warning";
     default:
       return "default";
   }
@@ -1,16 +1,34 @@
-import { useCallback, useMemo, useState } from "react";
-import type { CollectionManifestMeta } from "../types";
+import { useCallback, useEffect, useMemo, useState } from "react";
+import type {
+  CollectionDiff,
+  CollectionManifestMeta,
+  ManifestError,
+} from "../types";
 import {
+  diffCollections,
   listBuiltinCollections,
+  listenCollectionsChanged,
   refreshCollectionsFromDir,
 } from "../services/tauriApi";
 
+function formatManifestError(item: ManifestError): string {
+  const position =
+    item.line != null && item.column != null
+      ? ` (第 ${item.line} 行，第 ${item.column} 列)`
+      : "";
+  return `清单解析失败 [${item.kind}]${position}: ${item.message}`;
+}
+
 type UseCollectionsResult = {
   collections: CollectionManifestMeta[];
+  /** 最近一次加载时无法解析的清单。 */
+  manifestErrors: ManifestError[];
   selectedPaths: string[];
   selectedCount: number;
   dedupSelectedCount: number;
   loading: boolean;
+  /** 最近一次刷新外部目录时与内置快照的差异。 */
+  diff: CollectionDiff | null;
   refreshDir: string;
   setRefreshDir: (value: string) => void;
   loadCollections: () => Promise<void>;
@@ -27,18 +45,48 @@ export function useCollections(
   const [selectedPaths, setSelectedPaths] = useState<string[]>([]);
   const [loading, setLoading] = useState(false);
   const [refreshDir, setRefreshDir] = useState("");
+  const [diff, setDiff] = useState<CollectionDiff | null>(null);
+  const [manifestErrors, setManifestErrors] = useState<ManifestError[]>([]);
 
   const loadCollections = useCallback(async () => {
     setLoading(true);
     try {
       const result = await listBuiltinCollections();
-      setCollections(result);
-      const available = new Set(result.map((x) => x.path));
+      setCollections(result.manifests);
+      setManifestErrors(result.errors);
+      for (const item of result.errors) {
+        onLog(formatManifestError(item));
+      }
+      const available = new Set(result.manifests.map((x) => x.path));
       setSelectedPaths((prev) => prev.filter((p) => available.has(p)));
     } finally {
       setLoading(false);
     }
-  }, []);
+  }, [onLog]);
+
+  useEffect(() => {
+    let unlisten: (() => void) | null = null;
+
+    listenCollectionsChanged((payload) => {
+      onLog(
+        `外部 collections 已变化: 更新 ${payload.changed.length}，删除 ${payload.removed.length}，解析失败 ${payload.errors.length}`,
+      );
+      // 重新加载会逐个记录解析失败的清单。
+      loadCollections().catch((e) =>
+        onLog(`加载 collections 失败: ${String(e)}`),
+      );
+    })
+      .then((fn) => {
+        unlisten = fn;
+      })
+      .catch((e) => onLog(`监听 collections 变化失败: ${String(e)}`));
+
+    return () => {
+      if (unlisten) {
+        unlisten();
+      }
+    };
+  }, [loadCollections, onLog]);
 
   const refreshFromDir = useCallback(async () => {
     const dir = refreshDir.trim();
@@ -52,6 +100,16 @@ export function useCollections(
       await refreshCollectionsFromDir(dir);
       await loadCollections();
       onLog(`已刷新 collections: ${dir}`);
+      try {
+        const result = await diffCollections();
+        setDiff(result);
+        onLog(
+          `与内置快照相比: 新增 ID ${result.addedIds.length}，移除 ID ${result.removedIds.length}，变化清单 ${result.manifests.length}，未参与比较 ${result.errors.length}`,
+        );
+      } catch (e) {
+        setDiff(null);
+        onLog(`比较 collections 失败: ${String(e)}`);
+      }
     } finally {
       setLoading(false);
     }
@@ -81,10 +139,12 @@ export function useCollections(
 
   return {
     collections,
+    manifestErrors,
     selectedPaths,
     selectedCount: selectedPaths.length,
     dedupSelectedCount,
     loading,
+    diff,
     refreshDir,
     setRefreshDir,
     loadCollections,
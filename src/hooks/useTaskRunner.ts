@@ -4,6 +4,8 @@ import {
   cancelTask,
   getTaskState,
   listenTaskEvent,
+  pauseTask,
+  resumeTask,
   startDownloadTask,
 } from "../services/tauriApi";
 
@@ -16,6 +18,8 @@ type UseTaskRunnerResult = {
   clearLogs: () => void;
   runTask: (input: DownloadTaskInput) => Promise<void>;
   requestCancel: () => Promise<void>;
+  requestPause: () => Promise<void>;
+  requestResume: () => Promise<void>;
 };
 
 const FINAL_STATUSES = new Set(["completed", "failed", "cancelled"]);
@@ -65,6 +69,22 @@ export function useTaskRunner(): UseTaskRunnerResult {
     appendLog(`已请求取消任务: ${taskId}`);
   }, [appendLog, taskId]);
 
+  const requestPause = useCallback(async () => {
+    if (!taskId) {
+      return;
+    }
+    await pauseTask(taskId);
+    appendLog(`已请求暂停任务: ${taskId}`);
+  }, [appendLog, taskId]);
+
+  const requestResume = useCallback(async () => {
+    if (!taskId) {
+      return;
+    }
+    await resumeTask(taskId);
+    appendLog(`已请求恢复任务: ${taskId}`);
+  }, [appendLog, taskId]);
+
   useEffect(() => {
     let unlisten: (() => void) | null = null;
 
@@ -117,5 +137,7 @@ export function useTaskRunner(): UseTaskRunnerResult {
     clearLogs,
     runTask,
     requestCancel,
+    requestPause,
+    requestResume,
   };
 }
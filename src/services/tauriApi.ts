@@ -2,9 +2,22 @@ import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
 import { open, save } from "@tauri-apps/plugin-dialog";
 import type {
+  BundleTaskInput,
+  BundleVerifyReport,
+  ChartIndexEntry,
+  ChartIndexSummary,
+  ChartQuery,
+  CollectionDiff,
+  CollectionExportReport,
+  CollectionListing,
   CollectionManifestMeta,
+  CollectionsChanged,
+  ManifestSetOp,
   DownloadTaskInput,
+  LibraryScanReport,
+  RetryCredentials,
   TaskEvent,
+  TaskRecord,
   TaskState,
 } from "../types";
 
@@ -29,34 +42,136 @@ function ensureAdxExtension(path: string): string {
   return `${path}.adx`;
 }
 
-export async function listBuiltinCollections(): Promise<CollectionManifestMeta[]> {
-  return invoke<CollectionManifestMeta[]>("list_builtin_collections");
+export async function listBuiltinCollections(): Promise<CollectionListing> {
+  return invoke<CollectionListing>("list_builtin_collections");
 }
 
 export async function refreshCollectionsFromDir(dir: string): Promise<void> {
   await invoke("refresh_collections_from_dir", { dir });
 }
 
+/** 省略参数时比较内置快照与当前外部 collections 目录。 */
+export async function diffCollections(oldRoot?: string, newRoot?: string): Promise<CollectionDiff> {
+  return invoke<CollectionDiff>("diff_collections", { oldRoot, newRoot });
+}
+
+/** 以下清单编辑命令写入 `root`（省略时为当前外部 collections 目录），并把它设为当前外部目录。 */
+export async function createManifest(
+  folder: string,
+  name: string,
+  levelIds: string[],
+  root?: string,
+): Promise<CollectionManifestMeta> {
+  return invoke<CollectionManifestMeta>("create_manifest", { root, folder, name, levelIds });
+}
+
+export async function updateManifest(
+  path: string,
+  changes: { addIds?: string[]; removeIds?: string[]; name?: string },
+  root?: string,
+): Promise<CollectionManifestMeta> {
+  return invoke<CollectionManifestMeta>("update_manifest", { root, path, ...changes });
+}
+
+export async function combineManifests(
+  op: ManifestSetOp,
+  paths: string[],
+  folder: string,
+  name: string,
+  root?: string,
+): Promise<CollectionManifestMeta> {
+  return invoke<CollectionManifestMeta>("combine_manifests", { root, op, paths, folder, name });
+}
+
+/** 写出 `<targetDir>/collections/<文件夹>/manifest.json`；给出 localDir 时只保留本地已有的谱面。 */
+export async function exportCollections(
+  targetDir: string,
+  manifestPaths: string[],
+  localDir?: string,
+): Promise<CollectionExportReport> {
+  return invoke<CollectionExportReport>("export_collections", { targetDir, manifestPaths, localDir });
+}
+
 export async function startDownloadTask(
   input: DownloadTaskInput,
 ): Promise<StartTaskResult> {
   return invoke<StartTaskResult>("start_download_task", { input });
 }
 
+export async function retryFailedItems(
+  taskId: string,
+  credentials?: RetryCredentials,
+): Promise<StartTaskResult> {
+  return invoke<StartTaskResult>("retry_failed_items", { taskId, credentials });
+}
+
+export async function createBundle(input: BundleTaskInput): Promise<StartTaskResult> {
+  return invoke<StartTaskResult>("create_bundle", { input });
+}
+
+export async function verifyBundle(bundlePath: string): Promise<BundleVerifyReport> {
+  return invoke<BundleVerifyReport>("verify_bundle", { bundlePath });
+}
+
 export async function cancelTask(taskId: string): Promise<void> {
   await invoke("cancel_task", { taskId });
 }
 
+export async function pauseTask(taskId: string): Promise<void> {
+  await invoke("pause_task", { taskId });
+}
+
+export async function resumeTask(taskId: string): Promise<void> {
+  await invoke("resume_task", { taskId });
+}
+
 export async function getTaskState(taskId: string): Promise<TaskState | null> {
   return invoke<TaskState | null>("get_task_state", { taskId });
 }
 
+export async function scanLibrary(
+  outputDir: string,
+  manifestPaths: string[],
+): Promise<LibraryScanReport> {
+  return invoke<LibraryScanReport>("scan_library", { outputDir, manifestPaths });
+}
+
+export async function buildChartIndex(
+  outputDir: string,
+  manifestPaths?: string[],
+): Promise<ChartIndexSummary> {
+  return invoke<ChartIndexSummary>("build_chart_index", { outputDir, manifestPaths });
+}
+
+export async function queryCharts(
+  outputDir: string,
+  query: ChartQuery,
+): Promise<ChartIndexEntry[]> {
+  return invoke<ChartIndexEntry[]>("query_charts", { outputDir, query });
+}
+
+export async function listTasks(): Promise<TaskRecord[]> {
+  return invoke<TaskRecord[]>("list_tasks");
+}
+
+export async function deleteTask(taskId: string): Promise<void> {
+  await invoke("delete_task", { taskId });
+}
+
 export async function listenTaskEvent(
   cb: (event: TaskEvent) => void,
 ): Promise<() => void> {
   return listen<TaskEvent>("task_event", (evt) => cb(evt.payload));
 }
 
+export async function listenCollectionsChanged(
+  cb: (event: CollectionsChanged) => void,
+): Promise<() => void> {
+  return listen<CollectionsChanged>("collections_changed", (evt) =>
+    cb(evt.payload),
+  );
+}
+
 export async function pickDirectory(
   defaultPath?: string,
 ): Promise<string | null> {
@@ -7,6 +7,67 @@ export type CollectionManifestMeta = {
   source: "builtin" | "overlay";
 };
 
+export type ManifestError = {
+  path: string;
+  kind: "io" | "json" | "missing_level_ids" | "empty";
+  message: string;
+  /** JSON 错误所在的行与列。 */
+  line?: number | null;
+  column?: number | null;
+};
+
+export type CollectionListing = {
+  manifests: CollectionManifestMeta[];
+  /** 无法解析的清单；不影响其他清单。 */
+  errors: ManifestError[];
+};
+
+/** 外部 collections 目录中的清单被新增、修改或删除（`collections_changed` 事件）。 */
+export type CollectionsChanged = {
+  root: string;
+  changed: CollectionManifestMeta[];
+  removed: string[];
+  errors: ManifestError[];
+};
+
+export type ManifestDiff = {
+  relativePath: string;
+  name: string;
+  status: "added" | "removed" | "changed" | "renamed";
+  previousName?: string;
+  previousRelativePath?: string;
+  addedIds: string[];
+  removedIds: string[];
+};
+
+export type ExportedManifest = {
+  sourcePath: string;
+  folder: string;
+  name: string;
+  path: string;
+  levelCount: number;
+  removedIds: string[];
+};
+
+export type CollectionExportReport = {
+  collectionsDir: string;
+  exported: ExportedManifest[];
+  skipped: FailItem[];
+};
+
+export type ManifestSetOp = "union" | "intersection" | "difference";
+
+export type CollectionDiff = {
+  oldRoot: string;
+  newRoot: string;
+  manifests: ManifestDiff[];
+  unchangedCount: number;
+  addedIds: string[];
+  removedIds: string[];
+  /** 无法解析、未参与比较的清单。 */
+  errors: ManifestError[];
+};
+
 export type DownloadTaskInput = {
   selectedManifestPaths: string[];
   outputDir: string;
@@ -18,8 +79,25 @@ export type DownloadTaskInput = {
   outputFormat: "adx" | "zip";
   autoBundle: boolean;
   bundleOutputPath?: string;
+  bundleConflictPolicy?: "skip" | "rename" | "keep_newest";
+  bundleMaxBytes?: number;
+  bundleMaxCharts?: number;
+  bundleAppend?: boolean;
+  upgradeBga?: boolean;
   retries?: number;
   requestIntervalMs?: number;
+  concurrency?: number;
+  levelIds?: string[];
+  apiBase?: string;
+  filter?: string;
+  metadataUrl?: string;
+};
+
+export type RetryCredentials = {
+  connectSid: string;
+  authMode: "key" | "captcha";
+  key?: string;
+  captcha?: string;
 };
 
 export type FailItem = {
@@ -29,19 +107,146 @@ export type FailItem = {
 
 export type TaskState = {
   taskId: string;
-  status: "pending" | "running" | "completed" | "failed" | "cancelled";
+  status: "pending" | "running" | "paused" | "completed" | "failed" | "cancelled";
   totalIds: number;
   processedIds: number;
   okCount: number;
   skipCount: number;
   failCount: number;
   newFilesCount: number;
-  bundleOutputPath?: string;
+  upgradedCount: number;
+  /** 独立整合任务中已写入整合包的谱面数。 */
+  bundledCount: number;
+  bundleOutputPaths: string[];
   failItems: FailItem[];
   logs: string[];
   startedAt?: string;
   endedAt?: string;
   message?: string;
+  retryOf?: string;
+  filterReport?: FilterReport;
+};
+
+export type BundleTaskInput = {
+  sourceDir?: string;
+  files?: string[];
+  manifestPaths?: string[];
+  outputPath?: string;
+  conflictPolicy?: "skip" | "rename" | "keep_newest";
+  maxBytes?: number;
+  maxCharts?: number;
+  perManifest?: boolean;
+  append?: boolean;
+};
+
+export type BundleChartCheck = {
+  levelId: string;
+  sourceFile: string;
+  status: "ok" | "missing" | "corrupt";
+  problems: string[];
+};
+
+export type BundleVerifyReport = {
+  bundlePath: string;
+  chartCount: number;
+  okCount: number;
+  missingCount: number;
+  corruptCount: number;
+  problems: BundleChartCheck[];
+  unindexedEntries: string[];
+};
+
+export type FilterRuleStat = {
+  rule: string;
+  removed: number;
+};
+
+export type FilterReport = {
+  expression: string;
+  inputCount: number;
+  keptCount: number;
+  unknownCount: number;
+  rules: FilterRuleStat[];
+};
+
+export type LibraryFile = {
+  fileName: string;
+  path: string;
+  levelId?: string;
+  size: number;
+  status: "ok" | "empty" | "corrupt";
+  problem?: string;
+  variant?: "bga" | "nobga";
+};
+
+export type ManifestCoverage = {
+  path: string;
+  name: string;
+  total: number;
+  present: number;
+  missingIds: string[];
+};
+
+export type LibraryScanReport = {
+  outputDir: string;
+  files: LibraryFile[];
+  okCount: number;
+  emptyCount: number;
+  corruptCount: number;
+  staleParts: string[];
+  /** 之前生成的整合包，不计入谱面。 */
+  bundles: string[];
+  manifests: ManifestCoverage[];
+  missingIds: string[];
+};
+
+export type DifficultyMeta = {
+  index: number;
+  name: string;
+  level?: string;
+  designer?: string;
+  hasChart: boolean;
+};
+
+export type ChartMeta = {
+  title?: string;
+  artist?: string;
+  designer?: string;
+  first?: number;
+  wholebpm?: string;
+  difficulties: DifficultyMeta[];
+  hasBga?: boolean;
+};
+
+export type ChartIndexEntry = {
+  levelId: string;
+  fileName: string;
+  folder: string;
+  size: number;
+  modified?: number;
+  meta: ChartMeta;
+};
+
+export type ChartIndexSummary = {
+  indexPath: string;
+  total: number;
+  parsed: number;
+  reused: number;
+  failItems: FailItem[];
+};
+
+export type ChartQuery = {
+  title?: string;
+  artist?: string;
+  charter?: string;
+  difficulty?: string;
+  levelMin?: number;
+  levelMax?: number;
+};
+
+export type TaskRecord = {
+  input: DownloadTaskInput;
+  state: TaskState;
 };
 
 export type TaskEvent = {
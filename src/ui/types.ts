@@ -8,10 +8,13 @@ export type DownloadFormValues = {
   captcha: string;
   outputFormat: "adx" | "zip";
   downloadNoBga: boolean;
+  upgradeBga: boolean;
   autoBundle: boolean;
   bundleOutputPath: string;
+  bundleMaxMb: number;
   retries: number;
   requestIntervalMs: number;
+  concurrency: number;
 };
 
 export type TaskStatusViewModel = {
@@ -37,11 +40,14 @@ export function toDownloadInput(
     key: values.authMode === "key" ? values.key.trim() : undefined,
     captcha: values.authMode === "captcha" ? values.captcha.trim() : undefined,
     downloadNoBga: values.downloadNoBga,
+    upgradeBga: values.upgradeBga,
     outputFormat: values.outputFormat,
     autoBundle: values.autoBundle,
     bundleOutputPath: values.bundleOutputPath.trim() || undefined,
+    bundleMaxBytes: values.bundleMaxMb > 0 ? values.bundleMaxMb * 1024 * 1024 : undefined,
     retries: values.retries,
     requestIntervalMs: values.requestIntervalMs,
+    concurrency: values.concurrency,
   };
 }
 
@@ -59,6 +65,6 @@ export function toTaskStatusViewModel(
     skipCount: state.skipCount,
     failCount: state.failCount,
     newFilesCount: state.newFilesCount,
-    bundleOutputPath: state.bundleOutputPath ?? "-",
+    bundleOutputPath: state.bundleOutputPaths.length > 0 ? state.bundleOutputPaths.join(", ") : "-",
   };
 }